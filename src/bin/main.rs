@@ -1,17 +1,40 @@
-use clap::{App, Arg};
-use std::{env, process};
+use clap::{App, AppSettings, Arg, ArgMatches};
+use std::env;
 use std::process::exit;
-use log::{error, info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::str::FromStr;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+use serde_json::json;
+use uuid::Uuid;
+use tracing::{error, warn};
 use window_post_snark_server::{utils};
-use window_post_snark_server::run::run;
-use window_post_snark_server::server::{SERVER_EXIT_TIME_OUT_AFTER_TASK_DONE_DEFAULT, SERVER_LOCK_TIME_OUT_DEFAULT, SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT};
+use window_post_snark_server::child;
+use window_post_snark_server::child::ProveWorkerRequest;
+use window_post_snark_server::client;
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::cpu;
+use window_post_snark_server::cpu::CpuSet;
+use window_post_snark_server::history;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::logging;
+use window_post_snark_server::memory;
+use window_post_snark_server::memory::MemoryEstimate;
+use window_post_snark_server::params;
+use window_post_snark_server::params::ParamFileState;
+use window_post_snark_server::run::{run, RunConfig};
+use window_post_snark_server::snark_proof_grpc::{GetServerStatusResponse, ListTasksRequest, SnarkTaskRequestParams, TaskRecord};
+use window_post_snark_server::status::{ShutdownPolicy, TaskStatus};
+use window_post_snark_server::tasks;
+use window_post_snark_server::trace;
 
 fn main() {
     utils::set_commit_env();
     let cmds = App::new("window-post-snark-server")
         .author(utils::author())
         .version(utils::version())
-        .subcommands(vec![run_cmd(), stop_cmd()]);
+        .subcommands(vec![run_cmd(), stop_cmd(), status_cmd(), submit_cmd(), prove_cmd(), params_cmd(), prove_worker_cmd()]);
     let mut c = cmds.clone();
     let matches = cmds.get_matches();
     match matches.subcommand_name() {
@@ -24,19 +47,176 @@ fn main() {
                 env::set_var("RUST_LOG", "info");
             }
 
-            fil_logger::init();
-            let port = run_matched.value_of("port").unwrap().to_string();
-            if run_matched.is_present("force") {
-                assert_eq!(can_run(true), true);
+            logging::init(run_matched.is_present("log-json"), run_matched.value_of("otlp-endpoint"));
+            let listen_addrs = match run_matched.values_of("listen") {
+                Some(addrs) => addrs.map(|a| a.parse::<ListenAddr>()).collect(),
+                None => ListenAddr::from_port(run_matched.value_of("port").unwrap())
+                    .map(|a| vec![a]),
+            };
+            let listen_addrs = match listen_addrs {
+                Ok(addrs) => addrs,
+                Err(e) => {
+                    error!("{}", e);
+                    exit(1)
+                }
+            };
+            let preload_sector_sizes = sector_sizes_of(run_matched, "preload");
+            let verify_sector_sizes = sector_sizes_of(run_matched, "verify-params");
+            if !verify_sector_sizes.is_empty() && !verify_params(&verify_sector_sizes) {
+                error!("params verify failed, server will not start");
+                exit(1)
+            }
+            let partition_workers = match run_matched.value_of("partition-workers").unwrap().parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    error!("partition-workers should be a positive number");
+                    exit(1)
+                }
+            };
+            // held until the process exits
+            let _instance_lock = if run_matched.is_present("force") {
+                None
             } else {
-                assert_eq!(can_run(false), true);
+                let instance = match run_matched.value_of("instance") {
+                    Some(name) => name.to_string(),
+                    None => utils::instance_name(&listen_addrs),
+                };
+                match utils::InstanceLock::acquire(&instance) {
+                    Ok(lock) => Some(lock),
+                    Err(e) => {
+                        error!("{}", e);
+                        exit(1)
+                    }
+                }
+            };
+            let prove_in_child = run_matched.is_present("prove-in-child");
+            let max_prove_time = run_matched.value_of("max-prove-time").map(|s| secs_of("max-prove-time", s));
+            let max_prove_time_by_sector_size = max_prove_time_by_sector_size_of(run_matched);
+            let cpu_set = CpuSet {
+                threads: run_matched.value_of("prove-threads").map(threads_of),
+                cores: run_matched.value_of("cpu-affinity").map(cores_of).unwrap_or_default(),
+            };
+            let slot_threads = run_matched.value_of("slot-threads").map(threads_of);
+            let slot_cpu_sets = match run_matched.values_of("slot-cpu-affinity") {
+                Some(slots) => slots.map(|cores| CpuSet { threads: slot_threads, cores: cores_of(cores) }).collect(),
+                None => vec![],
+            };
+            if !slot_cpu_sets.is_empty() {
+                warn!("--slot-cpu-affinity only pins circuit synthesis, the groth16 multiexp and fft of all partition workers run on the cores of --cpu-affinity");
+            }
+            let memory_estimates = memory_estimates_of(run_matched);
+            let data_dir = run_matched.value_of("data-dir").map(PathBuf::from);
+            let task_history_len = match run_matched.value_of("task-history").unwrap().parse::<usize>() {
+                Ok(n) => n,
+                Err(e) => {
+                    error!("task-history should be a number: {}", e);
+                    exit(1)
+                }
+            };
+            run(RunConfig {
+                listen_addrs,
+                preload_sector_sizes,
+                partition_workers,
+                prove_in_child,
+                max_prove_time,
+                max_prove_time_by_sector_size,
+                cpu_set,
+                slot_cpu_sets,
+                memory_estimates,
+                data_dir,
+                task_history_len,
+                admin_token: run_matched.value_of("admin-token").map(String::from),
+                ..RunConfig::default()
+            });
+            trace::shutdown();
+        }
+        Some("params") => {
+            let params_matched = matches.subcommand_matches("params").unwrap();
+            match params_matched.subcommand_matches("verify") {
+                Some(verify_matched) => {
+                    logging::init(false, None);
+                    let mut sector_sizes = sector_sizes_of(verify_matched, "sector-size");
+                    if sector_sizes.is_empty() {
+                        sector_sizes = params::manifest_sector_sizes();
+                    }
+                    if !verify_params(&sector_sizes) {
+                        exit(1)
+                    }
+                }
+                None => {
+                    println!("{}", params_matched.usage());
+                    exit(1)
+                }
+            }
+        }
+        Some(child::PROVE_WORKER_CMD) => {
+            // logs go to stderr, which the server passes through
+            env::set_var("RUST_LOG", "info");
+            logging::init_from_env();
+            let proved = child::run_prove_worker();
+            trace::shutdown();
+            if let Err(e) = proved {
+                error!("prove worker failed with error: {}", e);
+                exit(1)
+            }
+        }
+        Some("prove") => {
+            let prove_matched = matches.subcommand_matches("prove").unwrap();
+            env::set_var("RUST_BACKTRACE", "full");
+            if prove_matched.is_present("debug") {
+                env::set_var("RUST_LOG", "debug");
             }
-            run(port,SERVER_LOCK_TIME_OUT_DEFAULT,SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT,SERVER_EXIT_TIME_OUT_AFTER_TASK_DONE_DEFAULT)
+            logging::init(false, None);
+            let params = match submit_params_of(prove_matched) {
+                Ok(params) => params,
+                Err(e) => {
+                    error!("{}", e);
+                    exit(1)
+                }
+            };
+            let partition_workers = match prove_matched.value_of("partition-workers").unwrap().parse::<usize>() {
+                Ok(n) if n > 0 => n,
+                _ => {
+                    error!("partition-workers should be a positive number");
+                    exit(1)
+                }
+            };
+            let cpu_set = CpuSet {
+                threads: prove_matched.value_of("prove-threads").map(threads_of),
+                cores: prove_matched.value_of("cpu-affinity").map(cores_of).unwrap_or_default(),
+            };
+            prove(ProveWorkerRequest { task_info: tasks::set_task_info(&params), partition_workers, cpu_set, slot_cpu_sets: vec![] }, prove_matched.value_of("out").unwrap());
+        }
+        Some("submit") => {
+            let submit_matched = matches.subcommand_matches("submit").unwrap();
+            logging::init(false, None);
+            let params = match submit_params_of(submit_matched) {
+                Ok(params) => params,
+                Err(e) => {
+                    error!("{}", e);
+                    exit(1)
+                }
+            };
+            let time_out = secs_of("time-out", submit_matched.value_of("time-out").unwrap());
+            submit(submit_matched.value_of("addr").unwrap(), params, submit_matched.value_of("out").unwrap(), submit_matched.value_of("miner-id"), time_out);
+        }
+        Some("status") => {
+            let status_matched = matches.subcommand_matches("status").unwrap();
+            let failures = match status_matched.value_of("failures").unwrap().parse::<u32>() {
+                Ok(n) => n,
+                Err(e) => {
+                    eprintln!("failures should be a number: {}", e);
+                    exit(1)
+                }
+            };
+            status(status_matched.value_of("addr").unwrap(), failures, status_matched.is_present("json"));
         }
         Some("stop") => {
             let stop_matched = matches.subcommand_matches("stop").unwrap();
-            let pid = stop_matched.value_of("pid").unwrap().to_string();
-            stop(pid);
+            logging::init(false, None);
+            let policy = ShutdownPolicy::from_str(stop_matched.value_of("policy").unwrap()).unwrap();
+            let wait = stop_matched.value_of("wait").map(|s| secs_of("wait", s));
+            stop(stop_matched.value_of("addr").unwrap(), policy, stop_matched.value_of("admin-token"), wait);
         }
         _ => {
             c.print_help().unwrap();
@@ -48,57 +228,506 @@ fn main() {
 fn run_cmd() -> App<'static, 'static> {
     App::new("run").about("run window-post-snark-server").args(&[
         Arg::from_usage("-d, --debug 'print debug log'").required(false),
-        Arg::from_usage("-f, --force 'force run process without num limit'").required(false),
+        Arg::from_usage("-f, --force 'run without taking the instance lock'").required(false),
+        Arg::from_usage("--instance=[NAME] 'instance name the lock file is keyed by, default the first listen address'")
+            .required(false),
         Arg::from_usage("-p, --port=[PORT] 'specify server port'")
             .default_value("50051")
             .required(false),
+        Arg::from_usage("-l, --listen=[ADDR]... 'listen address, like 0.0.0.0:50051, [::]:50051 or unix:/path.sock, overrides --port'")
+            .multiple(true)
+            .number_of_values(1)
+            .required(false),
+        Arg::from_usage("--preload=[SIZES]... 'sector sizes whose window post params are loaded at startup, like 2KiB,32GiB, winning post params are still loaded by the first task, not with --prove-in-child, whose children load params of their own'")
+            .conflicts_with("prove-in-child")
+            .multiple(true)
+            .use_delimiter(true)
+            .required(false),
+        Arg::from_usage("--verify-params=[SIZES]... 'verify params files of these sector sizes before start, like 32GiB'")
+            .multiple(true)
+            .use_delimiter(true)
+            .required(false),
+        Arg::from_usage("--partition-workers=[N] 'partitions of one task proved in parallel, 1 proves them in a single call'")
+            .default_value("1")
+            .required(false),
+        Arg::from_usage("--prove-in-child 'prove every task in a child process, which gives its memory back when done'")
+            .required(false),
+        Arg::from_usage("--max-prove-time=[SECS] 'fail a task still proving after SECS seconds, default no limit'")
+            .required(false),
+        Arg::from_usage("--max-prove-time-by-size=[SIZE=SECS]... 'max prove time for a sector size, like 32GiB=1800'")
+            .multiple(true)
+            .use_delimiter(true)
+            .required(false),
+        // bellperson proves on a pool of its own, sized and pinned only by the process it starts in
+        Arg::from_usage("--prove-threads=[N] 'threads of the child proving a task, default one per core of --cpu-affinity or all cores, needs --prove-in-child'")
+            .requires("prove-in-child")
+            .required(false),
+        Arg::from_usage("--cpu-affinity=[CORES] 'cores the child proving a task runs on, like 0-15,32-47, needs --prove-in-child'")
+            .requires("prove-in-child")
+            .required(false),
+        Arg::from_usage("--slot-cpu-affinity=[CORES]... 'cores of each partition worker in turn, like --slot-cpu-affinity 0-15 --slot-cpu-affinity 16-31, needs --prove-in-child, only circuit synthesis follows them, the groth16 multiexp and fft of all workers share the cores of the child'")
+            .multiple(true)
+            .number_of_values(1)
+            .requires("prove-in-child")
+            .required(false),
+        Arg::from_usage("--slot-threads=[N] 'threads of each partition worker with --slot-cpu-affinity, default one per core, needs --prove-in-child'")
+            .requires("prove-in-child")
+            .required(false),
+        Arg::from_usage("--memory-estimate=[SIZE=BASE+PER_PARTITION]... 'peak memory of a task of a sector size, like 32GiB=4GiB+12GiB, a task over the available memory is refused'")
+            .multiple(true)
+            .use_delimiter(true)
+            .required(false),
+        Arg::from_usage("--log-json 'log in json, one object per line with the task fields of its spans'")
+            .required(false),
+        Arg::from_usage("--otlp-endpoint=[URL] 'export spans over OTLP/HTTP, like http://localhost:4318'")
+            .required(false),
+        Arg::from_usage("--admin-token=[TOKEN] 'token admin requests like stop must carry, without one they are only accepted from this host'")
+            .required(false),
+        Arg::from_usage("--data-dir=[DIR] 'keep the task history in DIR over restarts'")
+            .required(false),
+        Arg::from_usage("--task-history=[N] 'recent tasks kept in the history'")
+            .default_value("1000")
+            .required(false),
     ])
 }
 
+fn prove_worker_cmd() -> App<'static, 'static> {
+    App::new(child::PROVE_WORKER_CMD)
+        .about("prove one task read from stdin, started by the server in --prove-in-child mode")
+        .setting(AppSettings::Hidden)
+}
+
+fn params_cmd() -> App<'static, 'static> {
+    App::new("params").about("manage proof parameter files").subcommand(
+        App::new("verify")
+            .about("check window post params and verifying keys against the parameter manifest")
+            .arg(
+                Arg::from_usage("-s, --sector-size=[SIZES]... 'sector sizes to verify, like 32GiB, default all in manifest'")
+                    .multiple(true)
+                    .use_delimiter(true)
+                    .required(false),
+            ),
+    )
+}
+
+fn sector_sizes_of(matched: &ArgMatches, name: &str) -> Vec<u64> {
+    match matched.values_of(name) {
+        Some(sizes) => match sizes.map(params::parse_sector_size).collect() {
+            Ok(sizes) => sizes,
+            Err(e) => {
+                error!("{}", e);
+                exit(1)
+            }
+        },
+        None => vec![],
+    }
+}
+
+fn secs_of(flag: &str, s: &str) -> Duration {
+    match s.parse::<u64>() {
+        Ok(secs) if secs > 0 => Duration::from_secs(secs),
+        _ => {
+            error!("--{} should be a positive number of seconds: {}", flag, s);
+            exit(1)
+        }
+    }
+}
+
+fn threads_of(s: &str) -> usize {
+    match s.parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        _ => {
+            error!("threads should be a positive number: {}", s);
+            exit(1)
+        }
+    }
+}
+
+fn cores_of(s: &str) -> Vec<usize> {
+    match cpu::parse_cores(s) {
+        Ok(cores) => cores,
+        Err(e) => {
+            error!("{}", e);
+            exit(1)
+        }
+    }
+}
+
+fn max_prove_time_by_sector_size_of(matched: &ArgMatches) -> HashMap<u64, Duration> {
+    let mut by_sector_size = HashMap::new();
+    for v in matched.values_of("max-prove-time-by-size").into_iter().flatten() {
+        let (size, secs) = match v.split_once('=') {
+            Some(kv) => kv,
+            None => {
+                error!("max prove time by size should be like 32GiB=1800: {}", v);
+                exit(1)
+            }
+        };
+        match params::parse_sector_size(size) {
+            Ok(size) => by_sector_size.insert(size, secs_of("max-prove-time-by-size", secs)),
+            Err(e) => {
+                error!("{}", e);
+                exit(1)
+            }
+        };
+    }
+    by_sector_size
+}
+
+fn memory_estimates_of(matched: &ArgMatches) -> HashMap<u64, MemoryEstimate> {
+    let mut estimates = HashMap::new();
+    for v in matched.values_of("memory-estimate").into_iter().flatten() {
+        let parsed = match v.split_once('=') {
+            Some((size, estimate)) => params::parse_sector_size(size).and_then(|size| {
+                memory::parse_memory_estimate(estimate).map(|estimate| (size, estimate))
+            }),
+            None => {
+                error!("memory estimate should be like 32GiB=4GiB+12GiB: {}", v);
+                exit(1)
+            }
+        };
+        match parsed {
+            Ok((size, estimate)) => estimates.insert(size, estimate),
+            Err(e) => {
+                error!("{}", e);
+                exit(1)
+            }
+        };
+    }
+    estimates
+}
+
+// print a line per params file, return false if any of them is missing or corrupted
+fn verify_params(sector_sizes: &[u64]) -> bool {
+    let reports = match params::verify_params(sector_sizes) {
+        Ok(r) => r,
+        Err(e) => {
+            error!("verify params failed with error: {}", e);
+            return false;
+        }
+    };
+    let mut all_ok = true;
+    for r in reports {
+        match r.state {
+            ParamFileState::Ok => {}
+            ParamFileState::Corrupted => {
+                all_ok = false;
+                error!(
+                    "{:?} is corrupted, digest expected {} but got {}",
+                    r.path, r.expected_digest, r.digest
+                );
+            }
+            _ => all_ok = false,
+        }
+        println!("{}\t{}\t{}", r.state, r.sector_size, r.path.display());
+    }
+    all_ok
+}
+
 fn stop_cmd() -> App<'static, 'static> {
-    App::new("stop").about("stop window-post-snark-server").arg(
-        Arg::from_usage("-p, --pid=[PID] 'specify server pid'")
-            .default_value("")
+    App::new("stop").about("shut down a window-post-snark-server and wait for it to exit").args(&[
+        Arg::from_usage("-a, --addr=[ADDR] 'server address, like http://127.0.0.1:50051 or unix:/path.sock'")
+            .default_value("http://127.0.0.1:50051")
             .required(false),
-    )
+        Arg::from_usage("--policy=[POLICY] 'exit at once, after the current task is proved, or after its result is got back'")
+            .possible_values(&["Immediate", "AfterTask", "AfterResult"])
+            .default_value("AfterResult")
+            .required(false),
+        Arg::from_usage("--admin-token=[TOKEN] 'admin token the server runs with, not needed for a local server without one'")
+            .required(false),
+        Arg::from_usage("--wait=[SECS] 'give up waiting for the server to exit after SECS seconds, default wait until it exits'")
+            .required(false),
+    ])
+}
+
+fn stop(addr: &str, policy: ShutdownPolicy, admin_token: Option<&str>, wait: Option<Duration>) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let stopped = rt.block_on(async {
+        let mut client = SnarkClient::connect(addr, Duration::from_secs(10)).await?;
+        let accepted = client.shutdown(policy, admin_token).await?;
+        println!("shutdown {} accepted, server {}, task {:?} {}", policy, accepted.status, accepted.task_id, accepted.task_status);
+        client.wait_exit(wait).await
+    });
+    match stopped {
+        Ok(last) => {
+            println!("server {} exited", addr);
+            if let Some(s) = last {
+                println!("last status: {}, version: {}, tasks timed out: {}", s.status, s.version, s.tasks_timed_out);
+            }
+        }
+        Err(e) => {
+            error!("stop server {} failed with error: {}", addr, e);
+            exit(1)
+        }
+    }
 }
 
+fn status_cmd() -> App<'static, 'static> {
+    App::new("status").about("print the status of a running window-post-snark-server").args(&[
+        Arg::from_usage("-a, --addr=[ADDR] 'server address, like http://127.0.0.1:50051 or unix:/path.sock'")
+            .default_value("http://127.0.0.1:50051")
+            .required(false),
+        Arg::from_usage("--failures=[N] 'recent failed tasks to print'")
+            .default_value("5")
+            .required(false),
+        Arg::from_usage("--json 'print as json'").required(false),
+    ])
+}
 
-fn stop(pid_s: String) {
-    let pid;
-    if pid_s == String::default() {
-        pid = utils::read_pid(utils::lock_file_path().to_str().unwrap().to_string());
+// only read-only rpcs, the lock of the server is left alone
+fn status(addr: &str, failures: u32, json: bool) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let got = rt.block_on(async {
+        let mut client = SnarkClient::connect(addr, Duration::from_secs(10)).await?;
+        let status = client.status().await?;
+        // a scheduler keeps no task history, its status is still printed
+        let failed = if failures == 0 {
+            Ok(vec![])
+        } else {
+            client.list_tasks(ListTasksRequest { status: TaskStatus::Failed.to_string(), since: 0, until: 0, limit: failures }).await
+        };
+        Ok::<_, anyhow::Error>((status, failed))
+    });
+    let (s, failed) = match got {
+        Ok(got) => got,
+        Err(e) => {
+            eprintln!("get status of server {} failed with error: {}", addr, e);
+            exit(1)
+        }
+    };
+    if json {
+        println!("{}", status_json(addr, &s, &failed));
     } else {
-        pid = pid_s.parse::<u32>().unwrap()
+        print_status(addr, &s, &failed);
     }
-    process::Command::new("kill").arg(pid.to_string()).output().unwrap();
 }
 
-fn can_run(is_force: bool) -> bool {
-    if !is_force {
-        if utils::is_file_lock_exist() {
-            warn!("file lock existed,will check process is_running by pid");
-            if let Some(p) = utils::check_process_is_running_by_pid() {
-                error!("process double run, old process still running, pid: {}", p);
-                false
-            } else {
-                warn!("old process is not running, let's go on");
-                true
+fn print_status(addr: &str, s: &GetServerStatusResponse, failed: &anyhow::Result<Vec<TaskRecord>>) {
+    println!("server:     {}", addr);
+    println!("version:    {}", s.version);
+    println!("status:     {} for {:?}{}", s.status, Duration::from_secs(s.in_state_ms / 1000), if s.ready { "" } else { ", params still loading" });
+    if s.task_id.is_empty() {
+        println!("task:       none");
+    } else {
+        println!("task:       {} {}", s.task_id, s.task_status);
+    }
+    println!("time outs:  lock {:?}, result get back {:?}, exit after task done {:?}",
+             Duration::from_millis(s.lock_time_out_ms), Duration::from_millis(s.task_get_back_time_out_ms), Duration::from_millis(s.exit_time_out_after_task_done_ms));
+    let mut max_prove_times = vec![if s.max_prove_time_ms == 0 { "none".to_string() } else { format!("{:?}", Duration::from_millis(s.max_prove_time_ms)) }];
+    let mut by_size: Vec<_> = s.max_prove_time_ms_by_sector_size.iter().collect();
+    by_size.sort();
+    max_prove_times.extend(by_size.iter().map(|(size, ms)| format!("{} {:?}", size_name(**size), Duration::from_millis(**ms))));
+    println!("max prove:  {}", max_prove_times.join(", "));
+    println!("timed out:  {} tasks", s.tasks_timed_out);
+    if !s.shutdown.is_empty() {
+        println!("shutdown:   {}", s.shutdown);
+    }
+    match failed {
+        Ok(records) if records.is_empty() => println!("failures:   none recently"),
+        Ok(records) => {
+            println!("failures:");
+            for r in records {
+                println!("  {} {} {} ended {:?} ago: {}", r.task_id, r.proof_type, size_name(r.sector_size), ago(r.ended_at), r.error);
             }
-        } else {
-            let pid = &process::id().to_string().as_bytes().to_vec();
-            match utils::write_pid_into_file_lock(pid) {
-                Ok(_) => {
-                    info!("write pid into lock file success");
-                    true
-                }
-                Err(e) => {
-                    error!("write pid into lock file failed with error:{}", e);
-                    false
+        }
+        Err(e) => println!("failures:   unknown, {}", e),
+    }
+}
+
+fn status_json(addr: &str, s: &GetServerStatusResponse, failed: &anyhow::Result<Vec<TaskRecord>>) -> serde_json::Value {
+    let failures = match failed {
+        Ok(records) => records
+            .iter()
+            .map(|r| json!({
+                "task_id": r.task_id,
+                "client": r.client,
+                "proof_type": r.proof_type,
+                "sector_size": r.sector_size,
+                "submitted_at": r.submitted_at,
+                "ended_at": r.ended_at,
+                "duration_ms": r.duration_ms,
+                "error": r.error,
+            }))
+            .collect(),
+        Err(_) => serde_json::Value::Null,
+    };
+    json!({
+        "addr": addr,
+        "version": s.version,
+        "status": s.status,
+        "ready": s.ready,
+        "in_state_ms": s.in_state_ms,
+        "task_id": s.task_id,
+        "task_status": s.task_status,
+        "lock_time_out_ms": s.lock_time_out_ms,
+        "task_get_back_time_out_ms": s.task_get_back_time_out_ms,
+        "exit_time_out_after_task_done_ms": s.exit_time_out_after_task_done_ms,
+        "max_prove_time_ms": s.max_prove_time_ms,
+        "max_prove_time_ms_by_sector_size": s.max_prove_time_ms_by_sector_size,
+        "preload_sector_sizes": s.preload_sector_sizes,
+        "tasks_timed_out": s.tasks_timed_out,
+        "shutdown": s.shutdown,
+        "recent_failures": failures,
+    })
+}
+
+// like 32GiB, or plain bytes
+fn size_name(bytes: u64) -> String {
+    for (unit, shift) in [("GiB", 30), ("MiB", 20), ("KiB", 10)] {
+        if bytes >= 1 << shift && bytes % (1 << shift) == 0 {
+            return format!("{}{}", bytes >> shift, unit);
+        }
+    }
+    bytes.to_string()
+}
+
+fn ago(unix_millis: u64) -> Duration {
+    match history::from_unix_millis(unix_millis) {
+        Some(t) => Duration::from_secs(SystemTime::now().duration_since(t).unwrap_or_default().as_secs()),
+        None => Duration::default(),
+    }
+}
+
+fn submit_cmd() -> App<'static, 'static> {
+    App::new("submit").about("prove a task read from files on a running server and write the proof").args(&[
+        Arg::from_usage("-a, --addr=[ADDR] 'server address, like http://127.0.0.1:50051 or unix:/path.sock'")
+            .default_value("http://127.0.0.1:50051")
+            .required(false),
+        Arg::from_usage("--vanilla=<FILE> 'vanilla proof json'"),
+        Arg::from_usage("--pub-in=<FILE> 'public inputs json'"),
+        Arg::from_usage("--post-config=[FILE] 'post config json, the proof type follows it'")
+            .required(false),
+        Arg::from_usage("--proof-type=[TYPE] 'proof type, default the one of --post-config'")
+            .possible_values(&["WindowPoSt", "WinningPoSt", "SealCommitPhase2"])
+            .required(false),
+        Arg::from_usage("--replicas-len=<N> 'sectors of the task'"),
+        Arg::from_usage("-o, --out=<FILE> 'file the proof is written to'"),
+        Arg::from_usage("--task-id=[ID] 'task id, default a random one'")
+            .required(false),
+        Arg::from_usage("--miner-id=[ID] 'miner id the server keeps in its task history'")
+            .required(false),
+        Arg::from_usage("--time-out=[SECS] 'give up when the proof is not back after SECS seconds'")
+            .default_value("1800")
+            .required(false),
+    ])
+}
+
+// the same fields a miner sends, read from files
+fn submit_params_of(matched: &ArgMatches) -> anyhow::Result<SnarkTaskRequestParams> {
+    let read = |name: &str| -> anyhow::Result<Vec<u8>> {
+        match matched.value_of(name) {
+            Some(path) => fs::read(path).map_err(|e| anyhow::Error::msg(format!("read --{} {} failed: {}", name, path, e))),
+            None => Ok(vec![]),
+        }
+    };
+    let post_config = read("post-config")?;
+    let proof_type = match matched.value_of("proof-type") {
+        Some(t) => t.to_string(),
+        None if !post_config.is_empty() => client::proof_type_of(&post_config),
+        None => return Err(anyhow::Error::msg("either --post-config or --proof-type is needed")),
+    };
+    let replicas_len = matched.value_of("replicas-len").unwrap().parse::<u32>()
+        .map_err(|e| anyhow::Error::msg(format!("replicas-len should be a number: {}", e)))?;
+    Ok(SnarkTaskRequestParams {
+        task_id: matched.value_of("task-id").map(String::from).unwrap_or_else(|| Uuid::new_v4().to_string()),
+        vanilla_proof: read("vanilla")?,
+        pub_in: read("pub-in")?,
+        post_config,
+        replicas_len,
+        proof_type,
+    })
+}
+
+fn submit(addr: &str, params: SnarkTaskRequestParams, out: &str, miner_id: Option<&str>, time_out: Duration) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let task_id = params.task_id.clone();
+    let start = Instant::now();
+    let proved = rt.block_on(async {
+        let mut client = SnarkClient::connect(addr, Duration::from_secs(10)).await?;
+        client.set_prove_time_out(time_out);
+        if let Some(id) = miner_id {
+            client.set_miner_id(id);
+        }
+        println!("locking server {} for task {}", addr, task_id);
+        client.lock(&task_id).await?;
+        println!("submitting {} task {}", params.proof_type, task_id);
+        client.submit(params).await?;
+        let mut last_done = None;
+        let proof = client
+            .wait_result_with_progress(&task_id, start, |done, partitions| {
+                // partitions are known once proving started
+                if partitions > 0 && last_done != Some(done) {
+                    println!("proving: {}/{} partitions done", done, partitions);
+                    last_done = Some(done);
                 }
-            }
+            })
+            .await?;
+        Ok::<_, anyhow::Error>((proof, client.last_timings().cloned()))
+    });
+    let (proof, timings) = match proved {
+        Ok(proved) => proved,
+        Err(e) => {
+            error!("task {} failed with error: {}", task_id, e);
+            exit(1)
         }
-    } else {
-        true
+    };
+    if let Err(e) = fs::write(out, &proof) {
+        error!("write proof to {} failed with error: {}", out, e);
+        exit(1)
+    }
+    println!("proof of {} bytes written to {} after {:?}", proof.len(), out, start.elapsed());
+    if let Some(t) = timings {
+        println!("timings: decode {}ms, setup {}ms, params load {}ms, prove {}ms, total {}ms", t.decode_ms, t.setup_ms, t.params_load_ms, t.prove_ms, t.total_ms);
+    }
+}
+
+fn prove_cmd() -> App<'static, 'static> {
+    App::new("prove").about("prove a task read from files in this process, the way the server does, and write the proof").args(&[
+        Arg::from_usage("-d, --debug 'print debug log'").required(false),
+        Arg::from_usage("--vanilla=<FILE> 'vanilla proof json'"),
+        Arg::from_usage("--pub-in=<FILE> 'public inputs json'"),
+        Arg::from_usage("--post-config=[FILE] 'post config json, the proof type follows it'")
+            .required(false),
+        Arg::from_usage("--proof-type=[TYPE] 'proof type, default the one of --post-config'")
+            .possible_values(&["WindowPoSt", "WinningPoSt", "SealCommitPhase2"])
+            .required(false),
+        Arg::from_usage("--replicas-len=<N> 'sectors of the task'"),
+        Arg::from_usage("-o, --out=<FILE> 'file the proof is written to'"),
+        Arg::from_usage("--task-id=[ID] 'task id the logs carry, default a random one'")
+            .required(false),
+        Arg::from_usage("--partition-workers=[N] 'partitions proved in parallel, 1 proves them in a single call'")
+            .default_value("1")
+            .required(false),
+        Arg::from_usage("--prove-threads=[N] 'threads of the proof, default one per core of --cpu-affinity or all cores'")
+            .required(false),
+        Arg::from_usage("--cpu-affinity=[CORES] 'cores the proof runs on, like 0-15,32-47'")
+            .required(false),
+    ])
+}
+
+fn prove(request: ProveWorkerRequest, out: &str) {
+    let t = &request.task_info;
+    println!("proving {} task {} of {} sectors", t.proof_type, t.task_id, t.replicas_len);
+    let start = Instant::now();
+    let (result, timings) = match child::prove_alone(request) {
+        Ok(proved) => proved,
+        Err(e) => {
+            error!("prove failed to start with error: {:?}", e);
+            exit(1)
+        }
+    };
+    println!("timings: {}", timings);
+    let proof = match result {
+        Ok(proof) => proof,
+        Err(e) => {
+            // the whole chain of causes, with the backtrace when there is one
+            error!("prove failed after {:?} with error: {:?}", start.elapsed(), e);
+            exit(1)
+        }
+    };
+    if let Err(e) = fs::write(out, &proof) {
+        error!("write proof to {} failed with error: {}", out, e);
+        exit(1)
     }
+    println!("proof of {} bytes written to {} after {:?}", proof.len(), out, start.elapsed());
 }
@@ -0,0 +1,69 @@
+use clap::{App, Arg};
+use std::env;
+use std::process::exit;
+use tracing::error;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::logging;
+use window_post_snark_server::run::run_scheduler;
+use window_post_snark_server::utils;
+
+fn main() {
+    utils::set_commit_env();
+    let cmds = App::new("window-post-snark-scheduler")
+        .author(utils::author())
+        .version(utils::version())
+        .subcommands(vec![run_cmd()]);
+    let mut c = cmds.clone();
+    let matches = cmds.get_matches();
+    match matches.subcommand_name() {
+        Some("run") => {
+            let run_matched = matches.subcommand_matches("run").unwrap();
+            if run_matched.is_present("debug") {
+                env::set_var("RUST_LOG", "debug");
+            } else {
+                env::set_var("RUST_LOG", "info");
+            }
+            logging::init(run_matched.is_present("log-json"), None);
+
+            let listen_addrs = match run_matched
+                .values_of("listen")
+                .unwrap()
+                .map(|a| a.parse::<ListenAddr>())
+                .collect()
+            {
+                Ok(addrs) => addrs,
+                Err(e) => {
+                    error!("{}", e);
+                    exit(1)
+                }
+            };
+            let backends = run_matched
+                .values_of("backend")
+                .unwrap()
+                .map(|b| b.to_string())
+                .collect();
+            run_scheduler(listen_addrs, backends)
+        }
+        _ => {
+            c.print_help().unwrap();
+            exit(1)
+        }
+    }
+}
+
+fn run_cmd() -> App<'static, 'static> {
+    App::new("run")
+        .about("run window-post-snark-scheduler in front of a pool of snark servers")
+        .args(&[
+            Arg::from_usage("-d, --debug 'print debug log'").required(false),
+            Arg::from_usage("--log-json 'log in json, one object per line'").required(false),
+            Arg::from_usage("-l, --listen=[ADDR]... 'listen address, like 0.0.0.0:50050, [::]:50050 or unix:/path.sock'")
+                .multiple(true)
+                .number_of_values(1)
+                .default_value("0.0.0.0:50050"),
+            Arg::from_usage("-b, --backend=<URI>... 'snark server, like http://10.0.0.5:50051 or unix:/path.sock'")
+                .multiple(true)
+                .number_of_values(1)
+                .required(true),
+        ])
+}
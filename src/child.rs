@@ -0,0 +1,190 @@
+use crate::cpu::CpuSet;
+use crate::error::Error;
+use crate::handler::TaskContext;
+use crate::server::ServerInfo;
+use crate::tasks;
+use crate::tasks::TaskInfo;
+use crate::timings::PhaseTimings;
+use crate::trace;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tracing::{error, info};
+
+/// hidden subcommand of the server binary that proves one task read from stdin
+pub const PROVE_WORKER_CMD: &str = "prove-worker";
+
+/// What the server writes to the stdin of a prove worker
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProveWorkerRequest {
+    pub task_info: TaskInfo,
+    pub partition_workers: usize,
+    pub cpu_set: CpuSet,
+    pub slot_cpu_sets: Vec<CpuSet>,
+}
+
+/// What a prove worker writes to its stdout, the proof or the error message
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProveWorkerResponse {
+    pub result: std::result::Result<Vec<u8>, String>,
+    pub timings: PhaseTimings,
+}
+
+/// prove the task in a child process of `exe`, so its memory is given back when it exits,
+/// a crash only fails this task and killing the child cancels it
+pub fn prove_in_child(
+    exe: &Path,
+    mut task_info: TaskInfo,
+    generation: u64,
+    srv_info: &Arc<Mutex<ServerInfo>>,
+) -> Result<Vec<u8>> {
+    // the spans of the child nest under the task span of the server
+    let headers = trace::to_headers(&trace::current_context());
+    if !headers.is_empty() {
+        task_info.trace_context = headers;
+    }
+    let request = match srv_info.lock() {
+        Ok(si) => ProveWorkerRequest {
+            task_info,
+            partition_workers: si.partition_workers,
+            cpu_set: si.cpu_set.clone(),
+            slot_cpu_sets: si.slot_cpu_sets.clone(),
+        },
+        Err(e) => return Err(anyhow::Error::msg(e.to_string())),
+    };
+    let task_id = request.task_info.task_id.clone();
+    let request = serde_json::to_vec(&request)?;
+
+    let mut child = Command::new(exe)
+        .arg(PROVE_WORKER_CMD)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    info!("task {} proving in child process {}", task_id, child.id());
+    set_prove_worker_pid(srv_info, Some(child.id()));
+
+    // the worker reads all of stdin before it writes anything, so this can not dead lock
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(&request),
+        None => Ok(()),
+    };
+    let mut stdout = vec![];
+    let read = match child.stdout.take() {
+        Some(mut out) => out.read_to_end(&mut stdout).map(|_| ()),
+        None => Ok(()),
+    };
+    // the pid is cleared before the child is reaped, so it is never reused while still set
+    let exited = wait_exited(child.id());
+    set_prove_worker_pid(srv_info, None);
+    let status = child.wait();
+    exited?;
+    let status = status?;
+    read?;
+    if let Err(e) = written {
+        error!("write task to prove worker failed with error: {}", e);
+    }
+
+    if stdout.is_empty() {
+        return Err(anyhow::Error::from(Error::ProveWorkerFailed(format!(
+            "exited with {}",
+            status
+        ))));
+    }
+    let response = serde_json::from_slice::<ProveWorkerResponse>(&stdout)?;
+    TaskContext::new(srv_info.clone(), generation).add_timings(&response.timings);
+    match response.result {
+        Ok(proof) => Ok(proof),
+        Err(e) => Err(anyhow::Error::msg(e)),
+    }
+}
+
+/// kill the prove worker of the current task if there is one, the task then fails
+pub fn kill_prove_worker(srv_info: &Arc<Mutex<ServerInfo>>) -> Result<bool> {
+    // the lock is held while killing, a set pid is then a child not reaped yet
+    match srv_info.lock() {
+        Ok(si) => match si.prove_worker_pid {
+            Some(pid) => {
+                info!("kill prove worker {}", pid);
+                if unsafe { libc::kill(pid as libc::pid_t, libc::SIGKILL) } != 0 {
+                    return Err(anyhow::Error::from(io::Error::last_os_error()));
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        },
+        Err(e) => Err(anyhow::Error::msg(e.to_string())),
+    }
+}
+
+// wait until the child exits, but leave it to be reaped so its pid is not reused
+fn wait_exited(pid: u32) -> io::Result<()> {
+    loop {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        let r = unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        };
+        if r == 0 {
+            return Ok(());
+        }
+        let e = io::Error::last_os_error();
+        if e.kind() != io::ErrorKind::Interrupted {
+            return Err(e);
+        }
+    }
+}
+
+/// entry of the prove worker process, logs go to stderr as stdout carries the response
+pub fn run_prove_worker() -> Result<()> {
+    let mut input = vec![];
+    io::stdin().read_to_end(&mut input)?;
+    let request = serde_json::from_slice::<ProveWorkerRequest>(&input)?;
+    let (result, timings) = prove_alone(request)?;
+    let response = ProveWorkerResponse {
+        result: result.map_err(|e| e.to_string()),
+        timings,
+    };
+    let mut stdout = io::stdout();
+    serde_json::to_writer(&mut stdout, &response)?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// prove the task in this process, with none of the server around it, the way a prove worker
+/// does, and return the proof or its error along with the phase timings so far
+pub fn prove_alone(request: ProveWorkerRequest) -> Result<(Result<Vec<u8>>, PhaseTimings)> {
+    // unlike in the server, the limits cover the thread pool of bellperson as well
+    request.cpu_set.apply_to_process()?;
+    let srv_info = Arc::new(Mutex::new(ServerInfo {
+        partition_workers: request.partition_workers,
+        cpu_set: request.cpu_set,
+        slot_cpu_sets: request.slot_cpu_sets,
+        ..ServerInfo::default()
+    }));
+    let span = tasks::task_span(&request.task_info);
+    let _enter = span.enter();
+    // the only task of this server info
+    let ctx = TaskContext::new(srv_info, 0);
+    let start = Instant::now();
+    let result = tasks::prove_with_handler(request.task_info, &ctx);
+    let mut timings = ctx.timings()?;
+    timings.total = start.elapsed();
+    Ok((result, timings))
+}
+
+fn set_prove_worker_pid(srv_info: &Arc<Mutex<ServerInfo>>, pid: Option<u32>) {
+    match srv_info.lock() {
+        Ok(mut si) => si.prove_worker_pid = pid,
+        Err(e) => error!("get lock failed with error: {}", e),
+    }
+}
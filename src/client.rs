@@ -1,14 +1,727 @@
 use crate::error::{Error, Result};
+use crate::listen::UNIX_SOCKET_PREFIX;
+use crate::partition;
+use crate::server::{ADMIN_TOKEN_METADATA_KEY, MINER_ID_METADATA_KEY, SERVER_NOT_READY_MSG};
 use crate::snark_proof_grpc::snark_task_service_client::SnarkTaskServiceClient;
-use std::time::Duration;
-use tonic::transport::Channel;
-
-pub async fn new_client(
-    addr: &'static str,
-    timeout: Duration,
-) -> Result<SnarkTaskServiceClient<Channel>> {
-    match Channel::from_shared(addr)?.timeout(timeout).connect().await {
+use crate::snark_proof_grpc::{
+    GetServerStatusRequest, GetServerStatusResponse, GetTaskResultRequest, GetWorkerStatusRequest,
+    ListTasksRequest, PhaseTimings, ShutdownRequest, ShutdownResponse, SnarkTaskRequestParams,
+    TaskRecord, UnlockServerRequest,
+};
+use crate::status::{ProofType, ServerStatus, ShutdownPolicy};
+use crate::tasks;
+use crate::trace;
+use futures::future::join_all;
+use std::cmp::min;
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use tokio::net::UnixStream;
+use tonic::transport::{Channel, Endpoint, Uri};
+use tonic::{Code, Request, Status};
+use tower::service_fn;
+use tracing::{error, info, warn};
+
+pub const CLIENT_LOCK_WAIT_TIME_OUT_DEFAULT: Duration = Duration::from_secs(600);
+pub const CLIENT_PROVE_TIME_OUT_DEFAULT: Duration = Duration::from_secs(1800);
+pub const CLIENT_POLL_INTERVAL_DEFAULT: Duration = Duration::from_secs(1);
+pub const CLIENT_MAX_POLL_INTERVAL_DEFAULT: Duration = Duration::from_secs(10);
+pub const POOL_UNHEALTHY_FAILURES_DEFAULT: u64 = 3;
+pub const POOL_COOL_DOWN_DEFAULT: Duration = Duration::from_secs(60);
+pub const POOL_MAX_ATTEMPTS_DEFAULT: usize = 3;
+
+/// `addr` is either an http uri like `http://127.0.0.1:50051` or a unix socket like `unix:/run/snark.sock`
+pub async fn new_client(addr: &str, timeout: Duration) -> Result<SnarkTaskServiceClient<Channel>> {
+    let connected = match addr.strip_prefix(UNIX_SOCKET_PREFIX) {
+        Some(path) => {
+            let path = path.to_string();
+            // the uri is required by Endpoint but ignored by the connector
+            Endpoint::from_static("http://[::]:50051")
+                .timeout(timeout)
+                .connect_with_connector(service_fn(move |_: Uri| UnixStream::connect(path.clone())))
+                .await
+        }
+        None => {
+            Channel::from_shared(addr.to_string())?
+                .timeout(timeout)
+                .connect_timeout(timeout)
+                .connect()
+                .await
+        }
+    };
+    match connected {
         Ok(ch) => Ok(SnarkTaskServiceClient::new(ch)),
         Err(e) => Err(anyhow::Error::from(Error::NewClientFailed(e.to_string()))),
     }
 }
+
+/// Runs the whole task lifecycle against one server: lock, submit, poll result and unlock on error
+#[derive(Debug, Clone)]
+pub struct SnarkClient {
+    pub addr: String,
+    client: SnarkTaskServiceClient<Channel>,
+    lock_wait_time_out: Duration,
+    prove_time_out: Duration,
+    poll_interval: Duration,
+    max_poll_interval: Duration,
+    // phase timings the server sent with the last proof
+    last_timings: Option<PhaseTimings>,
+    // sent with every request, the server records it in its task history
+    miner_id: Option<String>,
+}
+
+impl SnarkClient {
+    pub async fn connect(addr: &str, timeout: Duration) -> Result<Self> {
+        Ok(SnarkClient {
+            addr: addr.to_string(),
+            client: new_client(addr, timeout).await?,
+            lock_wait_time_out: CLIENT_LOCK_WAIT_TIME_OUT_DEFAULT,
+            prove_time_out: CLIENT_PROVE_TIME_OUT_DEFAULT,
+            poll_interval: CLIENT_POLL_INTERVAL_DEFAULT,
+            max_poll_interval: CLIENT_MAX_POLL_INTERVAL_DEFAULT,
+            last_timings: None,
+            miner_id: None,
+        })
+    }
+
+    pub fn set_miner_id(&mut self, miner_id: &str) {
+        self.miner_id = Some(miner_id.to_string());
+    }
+
+    pub fn last_timings(&self) -> Option<&PhaseTimings> {
+        self.last_timings.as_ref()
+    }
+
+    pub fn set_lock_wait_time_out(&mut self, time_out: Duration) {
+        self.lock_wait_time_out = time_out;
+    }
+
+    pub fn set_prove_time_out(&mut self, time_out: Duration) {
+        self.prove_time_out = time_out;
+    }
+
+    pub fn set_poll_interval(&mut self, interval: Duration, max_interval: Duration) {
+        self.poll_interval = interval;
+        self.max_poll_interval = max_interval;
+    }
+
+    // the miner id and the trace of the caller go along every request
+    fn request<T>(&self, message: T) -> Request<T> {
+        let mut req = Request::new(message);
+        if let Some(id) = self.miner_id.as_ref().and_then(|id| id.parse().ok()) {
+            req.metadata_mut().insert(MINER_ID_METADATA_KEY, id);
+        }
+        trace::inject(&trace::current_context(), req.metadata_mut());
+        req
+    }
+
+    /// try to lock the server once, returns the status it answered with
+    pub async fn try_lock(&mut self, task_id: &str) -> Result<ServerStatus> {
+        match self
+            .client
+            .lock_server_if_free(self.request(GetWorkerStatusRequest {
+                task_id: task_id.to_string(),
+            }))
+            .await
+        {
+            Ok(r) => {
+                Ok(ServerStatus::from_str(&r.into_inner().msg).unwrap_or(ServerStatus::Unknown))
+            }
+            Err(s) => Err(anyhow::Error::from(status_to_error(&s))),
+        }
+    }
+
+    /// wait until the server is locked for `task_id`, backing off while it is busy or not ready
+    pub async fn lock(&mut self, task_id: &str) -> Result<()> {
+        let start = Instant::now();
+        let mut interval = self.poll_interval;
+        loop {
+            let last_error = match self.try_lock(task_id).await {
+                Ok(ServerStatus::Free) => return Ok(()),
+                Ok(s) => Error::ServerBusy(s.to_string()),
+                Err(e) => match e.downcast::<Error>() {
+                    Ok(Error::ServerNotReady(m)) => Error::ServerNotReady(m),
+                    Ok(e) => return Err(anyhow::Error::from(e)),
+                    Err(e) => return Err(e),
+                },
+            };
+            if Instant::now().duration_since(start) + interval > self.lock_wait_time_out {
+                return Err(anyhow::Error::from(last_error));
+            }
+            tokio::time::sleep(interval).await;
+            interval = min(interval * 2, self.max_poll_interval);
+        }
+    }
+
+    pub async fn unlock(&mut self, task_id: &str) -> Result<()> {
+        match self
+            .client
+            .unlock_server(self.request(UnlockServerRequest {
+                task_id: task_id.to_string(),
+            }))
+            .await
+        {
+            Ok(_) => Ok(()),
+            Err(s) => Err(anyhow::Error::from(status_to_error(&s))),
+        }
+    }
+
+    /// submit a task to a server locked by `lock`, the server is unlocked if it is refused
+    pub async fn submit(&mut self, params: SnarkTaskRequestParams) -> Result<()> {
+        let task_id = params.task_id.clone();
+        let req = self.request(params);
+        match self.client.do_snark_task(req).await {
+            Ok(_) => Ok(()),
+            Err(s) => {
+                if let Err(e) = self.unlock(&task_id).await {
+                    warn!("unlock server {} failed with error: {}", self.addr, e);
+                }
+                Err(anyhow::Error::from(status_to_error(&s)))
+            }
+        }
+    }
+
+    /// poll until the result of a submitted task is back, or `prove_time_out` passed since `start`
+    pub async fn wait_result(&mut self, task_id: &str, start: Instant) -> Result<Vec<u8>> {
+        self.wait_result_with_progress(task_id, start, |_, _| {})
+            .await
+    }
+
+    /// same as `wait_result`, `on_progress` gets the partitions done and the partitions of
+    /// the task each time the server answers it is still proving
+    pub async fn wait_result_with_progress(
+        &mut self,
+        task_id: &str,
+        start: Instant,
+        mut on_progress: impl FnMut(u32, u32),
+    ) -> Result<Vec<u8>> {
+        let mut interval = self.poll_interval;
+        loop {
+            match self
+                .client
+                .get_snark_task_result(self.request(GetTaskResultRequest {
+                    task_id: task_id.to_string(),
+                }))
+                .await
+            {
+                Ok(r) => {
+                    let r = r.into_inner();
+                    if r.msg == "ok" {
+                        self.last_timings = r.timings;
+                        return Ok(r.result);
+                    }
+                    on_progress(r.partitions_done, r.partitions);
+                }
+                Err(s) => return Err(anyhow::Error::from(status_to_error(&s))),
+            }
+            if Instant::now().duration_since(start) + interval > self.prove_time_out {
+                return Err(anyhow::Error::from(Error::TaskTimeOut(task_id.to_string())));
+            }
+            tokio::time::sleep(interval).await;
+            interval = min(interval * 2, self.max_poll_interval);
+        }
+    }
+
+    /// lock the server, run the post task and return the snark proof bytes
+    pub async fn prove(
+        &mut self,
+        task_id: &str,
+        vanilla_proof: Vec<u8>,
+        pub_in: Vec<u8>,
+        post_config: Vec<u8>,
+        replicas_len: u32,
+    ) -> Result<Vec<u8>> {
+        self.prove_task(SnarkTaskRequestParams {
+            task_id: task_id.to_string(),
+            vanilla_proof,
+            pub_in,
+            proof_type: proof_type_of(&post_config),
+            post_config,
+            replicas_len,
+        })
+        .await
+    }
+
+    /// same as `prove` for a task of any proof type, like a seal commit phase 2 one
+    pub async fn prove_task(&mut self, params: SnarkTaskRequestParams) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let task_id = params.task_id.clone();
+        self.lock(&task_id).await?;
+        info!("server {} locked by task {}", self.addr, task_id);
+        self.submit(params).await?;
+        self.wait_result(&task_id, start).await
+    }
+
+    pub async fn status(&mut self) -> Result<GetServerStatusResponse> {
+        match self
+            .client
+            .get_server_status(self.request(GetServerStatusRequest {}))
+            .await
+        {
+            Ok(r) => Ok(r.into_inner()),
+            Err(s) => Err(anyhow::Error::from(status_to_error(&s))),
+        }
+    }
+
+    /// ended tasks the server keeps, most recent first
+    pub async fn list_tasks(&mut self, filter: ListTasksRequest) -> Result<Vec<TaskRecord>> {
+        match self.client.list_tasks(self.request(filter)).await {
+            Ok(r) => Ok(r.into_inner().tasks),
+            Err(s) => Err(anyhow::Error::from(status_to_error(&s))),
+        }
+    }
+
+    /// ask the server to shut down, a server started with an admin token only accepts it with
+    /// the same token, one without only from local clients
+    pub async fn shutdown(
+        &mut self,
+        policy: ShutdownPolicy,
+        admin_token: Option<&str>,
+    ) -> Result<ShutdownResponse> {
+        let mut req = self.request(ShutdownRequest {
+            policy: policy.to_string(),
+        });
+        if let Some(token) = admin_token {
+            match token.parse() {
+                Ok(token) => {
+                    req.metadata_mut().insert(ADMIN_TOKEN_METADATA_KEY, token);
+                }
+                Err(_) => {
+                    return Err(anyhow::Error::from(Error::InvalidParameters(
+                        "admin token is not valid ascii".to_string(),
+                    )))
+                }
+            }
+        }
+        match self.client.shutdown(req).await {
+            Ok(r) => Ok(r.into_inner()),
+            Err(s) => Err(anyhow::Error::from(status_to_error(&s))),
+        }
+    }
+
+    /// poll the status until the server stops answering, which is once it exited,
+    /// returns the last status it answered with
+    pub async fn wait_exit(
+        &mut self,
+        time_out: Option<Duration>,
+    ) -> Result<Option<GetServerStatusResponse>> {
+        let start = Instant::now();
+        let mut last = None;
+        loop {
+            match self.status().await {
+                Ok(s) => last = Some(s),
+                Err(_) => return Ok(last),
+            }
+            if time_out.map_or(false, |t| Instant::now().duration_since(start) > t) {
+                return Err(anyhow::Error::from(Error::RpcFailed(format!(
+                    "server {} still running after {:?}",
+                    self.addr,
+                    Instant::now().duration_since(start)
+                ))));
+            }
+            tokio::time::sleep(self.poll_interval).await;
+        }
+    }
+}
+
+/// per server numbers a `SnarkClientPool` keeps to choose and skip servers
+#[derive(Debug, Clone)]
+pub struct PoolServerStats {
+    pub addr: String,
+    pub requests: u64,
+    pub failures: u64,
+    pub consecutive_failures: u64,
+    // moving average of rpc latency
+    pub latency: Option<Duration>,
+    // server is skipped until then after too many consecutive failures
+    pub unhealthy_until: Option<Instant>,
+}
+
+impl PoolServerStats {
+    fn new(addr: String) -> Self {
+        PoolServerStats {
+            addr,
+            requests: 0,
+            failures: 0,
+            consecutive_failures: 0,
+            latency: None,
+            unhealthy_until: None,
+        }
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        if self.requests == 0 {
+            0.0
+        } else {
+            self.failures as f64 / self.requests as f64
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        match self.unhealthy_until {
+            Some(t) => Instant::now() >= t,
+            None => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PoolServer {
+    stats: PoolServerStats,
+    client: Option<SnarkClient>,
+}
+
+/// Proves on whichever of several servers is free, failing over to another one if it errors
+#[derive(Debug, Clone)]
+pub struct SnarkClientPool {
+    servers: Arc<Mutex<Vec<PoolServer>>>,
+    client_time_out: Duration,
+    lock_wait_time_out: Duration,
+    prove_time_out: Duration,
+    poll_interval: Duration,
+    max_poll_interval: Duration,
+    unhealthy_failures: u64,
+    cool_down: Duration,
+    max_attempts: usize,
+}
+
+impl SnarkClientPool {
+    /// servers are connected lazily, so dead endpoints do not fail the pool creation
+    pub fn new(addrs: Vec<String>, client_time_out: Duration) -> Self {
+        SnarkClientPool {
+            servers: Arc::new(Mutex::new(
+                addrs
+                    .into_iter()
+                    .map(|addr| PoolServer {
+                        stats: PoolServerStats::new(addr),
+                        client: None,
+                    })
+                    .collect(),
+            )),
+            client_time_out,
+            lock_wait_time_out: CLIENT_LOCK_WAIT_TIME_OUT_DEFAULT,
+            prove_time_out: CLIENT_PROVE_TIME_OUT_DEFAULT,
+            poll_interval: CLIENT_POLL_INTERVAL_DEFAULT,
+            max_poll_interval: CLIENT_MAX_POLL_INTERVAL_DEFAULT,
+            unhealthy_failures: POOL_UNHEALTHY_FAILURES_DEFAULT,
+            cool_down: POOL_COOL_DOWN_DEFAULT,
+            max_attempts: POOL_MAX_ATTEMPTS_DEFAULT,
+        }
+    }
+
+    pub fn set_lock_wait_time_out(&mut self, time_out: Duration) {
+        self.lock_wait_time_out = time_out;
+    }
+
+    pub fn set_prove_time_out(&mut self, time_out: Duration) {
+        self.prove_time_out = time_out;
+    }
+
+    pub fn set_poll_interval(&mut self, interval: Duration, max_interval: Duration) {
+        self.poll_interval = interval;
+        self.max_poll_interval = max_interval;
+    }
+
+    /// a server is skipped for `cool_down` after `unhealthy_failures` failures in a row
+    pub fn set_health_policy(&mut self, unhealthy_failures: u64, cool_down: Duration) {
+        self.unhealthy_failures = unhealthy_failures;
+        self.cool_down = cool_down;
+    }
+
+    pub fn set_max_attempts(&mut self, max_attempts: usize) {
+        self.max_attempts = max_attempts;
+    }
+
+    pub fn stats(&self) -> Result<Vec<PoolServerStats>> {
+        Ok(self.servers()?.iter().map(|s| s.stats.clone()).collect())
+    }
+
+    fn servers(&self) -> Result<MutexGuard<'_, Vec<PoolServer>>> {
+        match self.servers.lock() {
+            Ok(s) => Ok(s),
+            Err(e) => Err(anyhow::Error::msg(e.to_string())),
+        }
+    }
+
+    /// run the task on a free server, resubmitting to another one if the chosen server fails,
+    /// a task the server rejects or fails proving is not resubmitted
+    pub async fn prove(
+        &self,
+        task_id: &str,
+        vanilla_proof: Vec<u8>,
+        pub_in: Vec<u8>,
+        post_config: Vec<u8>,
+        replicas_len: u32,
+    ) -> Result<Vec<u8>> {
+        let start = Instant::now();
+        let params = SnarkTaskRequestParams {
+            task_id: task_id.to_string(),
+            vanilla_proof,
+            pub_in,
+            proof_type: proof_type_of(&post_config),
+            post_config,
+            replicas_len,
+        };
+        let mut excluded = HashSet::new();
+        let mut last_error =
+            anyhow::Error::from(Error::ServerBusy(ServerStatus::Unknown.to_string()));
+        let mut attempts = 0;
+        while attempts < self.max_attempts {
+            let mut client = match self.lock_any(task_id, &excluded).await {
+                Ok(c) => c,
+                Err(e) => {
+                    // no server left to fail over to
+                    if excluded.is_empty() {
+                        return Err(e);
+                    }
+                    break;
+                }
+            };
+            info!("server {} locked by task {}", client.addr, task_id);
+            let result = match client.submit(params.clone()).await {
+                Ok(_) => client.wait_result(task_id, start).await,
+                Err(e) => Err(e),
+            };
+            let e = match result {
+                Ok(r) => {
+                    self.record(&client.addr, None, true);
+                    return Ok(r);
+                }
+                Err(e) => e,
+            };
+            warn!(
+                "task {} failed on server {} with error: {}",
+                task_id, client.addr, e
+            );
+            match e.downcast_ref::<Error>() {
+                // the task itself is wrong, it would fail the same way on another server
+                Some(Error::TaskFailedWithError(_)) | Some(Error::InvalidParameters(_)) => {
+                    self.record(&client.addr, None, true);
+                    return Err(e);
+                }
+                // the server is fine but can not take this task now, try another one
+                Some(Error::InsufficientMemory(_)) => {}
+                _ => {
+                    self.record(&client.addr, None, false);
+                    attempts += 1;
+                }
+            }
+            excluded.insert(client.addr.clone());
+            last_error = e;
+        }
+        Err(last_error)
+    }
+
+    /// split a whole window post task by partition, prove the partitions in parallel on
+    /// different servers and merge the partition proofs into the final proof
+    pub async fn prove_partitioned(
+        &self,
+        task_id: &str,
+        vanilla_proof: Vec<u8>,
+        pub_in: Vec<u8>,
+        post_config: Vec<u8>,
+        replicas_len: u32,
+    ) -> Result<Vec<u8>> {
+        let partitions = partition::split_window_post_task(&SnarkTaskRequestParams {
+            task_id: task_id.to_string(),
+            vanilla_proof,
+            pub_in,
+            post_config,
+            replicas_len,
+            proof_type: ProofType::WindowPoSt.to_string(),
+        })?;
+        info!(
+            "task {} split into {} partitions",
+            task_id,
+            partitions.len()
+        );
+        let proofs = join_all(partitions.into_iter().map(|p| async move {
+            self.prove(
+                &p.task_id,
+                p.vanilla_proof,
+                p.pub_in,
+                p.post_config,
+                p.replicas_len,
+            )
+            .await
+        }))
+        .await;
+        let proofs = proofs.into_iter().collect::<Result<Vec<_>>>()?;
+        Ok(partition::merge_partition_proofs(proofs))
+    }
+
+    // lock candidates in parallel and keep the fastest free one, unlocking the others
+    async fn lock_any(&self, task_id: &str, excluded: &HashSet<String>) -> Result<SnarkClient> {
+        let lock_start = Instant::now();
+        let mut interval = self.poll_interval;
+        loop {
+            let candidates = self.candidates(excluded)?;
+            if candidates.is_empty() {
+                return Err(anyhow::Error::from(Error::ServerBusy(
+                    ServerStatus::Unknown.to_string(),
+                )));
+            }
+            let locked = join_all(
+                candidates
+                    .iter()
+                    .map(|addr| self.try_lock_on(addr, task_id)),
+            )
+            .await;
+            let mut free: Vec<(SnarkClient, Duration)> = vec![];
+            let mut last_error = Error::ServerBusy(ServerStatus::Unknown.to_string());
+            for l in locked {
+                match l {
+                    Ok((c, ServerStatus::Free, latency)) => free.push((c, latency)),
+                    Ok((_, s, _)) => last_error = Error::ServerBusy(s.to_string()),
+                    Err(e) => last_error = e,
+                }
+            }
+            free.sort_by_key(|(_, latency)| *latency);
+            let mut free = free.into_iter();
+            if let Some((chosen, _)) = free.next() {
+                for (mut c, _) in free {
+                    if let Err(e) = c.unlock(task_id).await {
+                        warn!("unlock server {} failed with error: {}", c.addr, e);
+                    }
+                }
+                return Ok(chosen);
+            }
+            if Instant::now().duration_since(lock_start) + interval > self.lock_wait_time_out {
+                return Err(anyhow::Error::from(last_error));
+            }
+            tokio::time::sleep(interval).await;
+            interval = min(interval * 2, self.max_poll_interval);
+        }
+    }
+
+    // healthy servers not excluded, or every server not excluded if none of them is healthy
+    fn candidates(&self, excluded: &HashSet<String>) -> Result<Vec<String>> {
+        let servers = self.servers()?;
+        let not_excluded = || servers.iter().filter(|s| !excluded.contains(&s.stats.addr));
+        let healthy: Vec<String> = not_excluded()
+            .filter(|s| s.stats.is_healthy())
+            .map(|s| s.stats.addr.clone())
+            .collect();
+        if healthy.is_empty() {
+            Ok(not_excluded().map(|s| s.stats.addr.clone()).collect())
+        } else {
+            Ok(healthy)
+        }
+    }
+
+    async fn try_lock_on(
+        &self,
+        addr: &str,
+        task_id: &str,
+    ) -> std::result::Result<(SnarkClient, ServerStatus, Duration), Error> {
+        let start = Instant::now();
+        let mut client = match self.client_of(addr).await {
+            Ok(c) => c,
+            Err(e) => {
+                self.record(addr, None, false);
+                return Err(Error::NewClientFailed(e.to_string()));
+            }
+        };
+        match client.try_lock(task_id).await {
+            Ok(s) => {
+                let latency = Instant::now().duration_since(start);
+                self.record(addr, Some(latency), true);
+                Ok((client, s, latency))
+            }
+            Err(e) => {
+                self.record(addr, None, false);
+                match e.downcast::<Error>() {
+                    Ok(e) => Err(e),
+                    Err(e) => Err(Error::RpcFailed(e.to_string())),
+                }
+            }
+        }
+    }
+
+    async fn client_of(&self, addr: &str) -> Result<SnarkClient> {
+        let cached = self
+            .servers()?
+            .iter()
+            .find(|s| s.stats.addr == addr)
+            .and_then(|s| s.client.clone());
+        if let Some(c) = cached {
+            return Ok(c);
+        }
+        let mut client = SnarkClient::connect(addr, self.client_time_out).await?;
+        client.set_prove_time_out(self.prove_time_out);
+        client.set_poll_interval(self.poll_interval, self.max_poll_interval);
+        if let Some(s) = self.servers()?.iter_mut().find(|s| s.stats.addr == addr) {
+            s.client = Some(client.clone());
+        }
+        Ok(client)
+    }
+
+    fn record(&self, addr: &str, latency: Option<Duration>, ok: bool) {
+        let mut servers = match self.servers() {
+            Ok(s) => s,
+            Err(e) => {
+                error!("get lock failed with error: {}", e);
+                return;
+            }
+        };
+        let s = match servers.iter_mut().find(|s| s.stats.addr == addr) {
+            Some(s) => s,
+            None => return,
+        };
+        s.stats.requests += 1;
+        if let Some(l) = latency {
+            s.stats.latency = Some(match s.stats.latency {
+                Some(avg) => (avg * 7 + l) / 8,
+                None => l,
+            });
+        }
+        if ok {
+            s.stats.consecutive_failures = 0;
+            s.stats.unhealthy_until = None;
+        } else {
+            s.stats.failures += 1;
+            s.stats.consecutive_failures += 1;
+            // reconnect next time, the server may have been restarted
+            s.client = None;
+            if s.stats.consecutive_failures >= self.unhealthy_failures {
+                warn!(
+                    "server {} failed {} times in a row, skip it for {:?}",
+                    addr, s.stats.consecutive_failures, self.cool_down
+                );
+                s.stats.unhealthy_until = Some(Instant::now() + self.cool_down);
+            }
+        }
+    }
+}
+
+/// the proof type follows the post config, a config that can not be parsed is rejected by the server
+pub fn proof_type_of(post_config: &Vec<u8>) -> String {
+    match tasks::get_post_config(post_config) {
+        Ok(c) => tasks::proof_type_of(&c).to_string(),
+        Err(_) => String::default(),
+    }
+}
+
+/// map a status from `SnarkTaskService` into the error the server raised
+pub fn status_to_error(s: &Status) -> Error {
+    let msg = s.message();
+    let task_failed_prefix = Error::TaskFailedWithError(String::default()).to_string();
+    let insufficient_memory_prefix = Error::InsufficientMemory(String::default()).to_string();
+    match s.code() {
+        Code::Aborted if msg.starts_with(&task_failed_prefix) => {
+            Error::TaskFailedWithError(msg[task_failed_prefix.len()..].to_string())
+        }
+        Code::Cancelled if msg == Error::NoTaskRunningOnSever.to_string() => {
+            Error::NoTaskRunningOnSever
+        }
+        Code::Unavailable if msg.starts_with(SERVER_NOT_READY_MSG) => {
+            Error::ServerNotReady(msg.to_string())
+        }
+        Code::InvalidArgument => Error::InvalidParameters(msg.to_string()),
+        Code::ResourceExhausted if msg.starts_with(&insufficient_memory_prefix) => {
+            Error::InsufficientMemory(msg[insufficient_memory_prefix.len()..].to_string())
+        }
+        _ => Error::RpcFailed(format!("{:?}: {}", s.code(), msg)),
+    }
+}
@@ -0,0 +1,155 @@
+use crate::error::Error;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::io;
+use tracing::{error, Span};
+
+/// Threads and cpu cores a proof may use, the default leaves both to rayon
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CpuSet {
+    // none is one thread per core, or what rayon picks without cores
+    pub threads: Option<usize>,
+    // empty runs on any core
+    pub cores: Vec<usize>,
+}
+
+impl CpuSet {
+    pub fn is_default(&self) -> bool {
+        self.threads.is_none() && self.cores.is_empty()
+    }
+
+    pub fn num_threads(&self) -> Option<usize> {
+        match self.threads {
+            Some(n) => Some(n),
+            None if !self.cores.is_empty() => Some(self.cores.len()),
+            None => None,
+        }
+    }
+
+    /// run `f` in a rayon pool of this set, with every pool thread pinned to the cores,
+    /// the parallel parts of `f` then stay in the pool
+    pub fn install<R: Send>(&self, f: impl FnOnce() -> R + Send) -> Result<R> {
+        if self.is_default() {
+            return Ok(f());
+        }
+        let mut builder = rayon::ThreadPoolBuilder::new().thread_name(|i| format!("prove-{}", i));
+        if let Some(n) = self.num_threads() {
+            builder = builder.num_threads(n);
+        }
+        if !self.cores.is_empty() {
+            let cores = self.cores.clone();
+            builder = builder.start_handler(move |_| {
+                if let Err(e) = pin_current_thread(&cores) {
+                    error!(
+                        "pin prove thread to cores {:?} failed with error: {}",
+                        cores, e
+                    );
+                }
+            });
+        }
+        // log lines from the pool still belong to the task
+        let span = Span::current();
+        Ok(builder.build()?.install(move || span.in_scope(f)))
+    }
+
+    /// apply to the whole process before it starts any thread, which then all inherit the
+    /// cores, and size the thread pools of rayon and bellperson, which follows rayon's
+    pub fn apply_to_process(&self) -> Result<()> {
+        if let Some(n) = self.num_threads() {
+            env::set_var("RAYON_NUM_THREADS", n.to_string());
+        }
+        if !self.cores.is_empty() {
+            pin_current_thread(&self.cores)?;
+        }
+        Ok(())
+    }
+}
+
+/// cores a cpu set can hold, a core past them can not be pinned to
+#[cfg(target_os = "linux")]
+pub const CORES_MAX: usize = libc::CPU_SETSIZE as usize;
+#[cfg(not(target_os = "linux"))]
+pub const CORES_MAX: usize = 1024;
+
+/// parse cores like `0-7,16-23`
+pub fn parse_cores(s: &str) -> Result<Vec<usize>, Error> {
+    let mut cores = vec![];
+    for part in s.split(',') {
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let (first, last) = (parse_core(s, first)?, parse_core(s, last)?);
+                if first > last {
+                    return Err(Error::InvalidParameters(format!(
+                        "invalid cpu cores {}: {} is after {}",
+                        s, first, last
+                    )));
+                }
+                cores.extend(first..=last);
+            }
+            None => cores.push(parse_core(s, part)?),
+        }
+    }
+    cores.sort_unstable();
+    cores.dedup();
+    Ok(cores)
+}
+
+fn parse_core(s: &str, core: &str) -> Result<usize, Error> {
+    let core = core
+        .trim()
+        .parse::<usize>()
+        .map_err(|e| Error::InvalidParameters(format!("invalid cpu cores {}: {}", s, e)))?;
+    if core >= CORES_MAX {
+        return Err(Error::InvalidParameters(format!(
+            "invalid cpu cores {}: core {} is over the max of {}",
+            s,
+            core,
+            CORES_MAX - 1
+        )));
+    }
+    Ok(core)
+}
+
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cores: &[usize]) -> io::Result<()> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for core in cores {
+            libc::CPU_SET(*core, &mut set);
+        }
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+pub fn current_thread_cores() -> io::Result<Vec<usize>> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        if libc::sched_getaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((0..libc::CPU_SETSIZE as usize)
+            .filter(|core| libc::CPU_ISSET(*core, &set))
+            .collect())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cores: &[usize]) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "cpu affinity is only supported on linux",
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn current_thread_cores() -> io::Result<Vec<usize>> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "cpu affinity is only supported on linux",
+    ))
+}
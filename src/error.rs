@@ -17,10 +17,31 @@ pub enum Error {
     TaskFailedWithError(String),
     #[error("new client failed with error: {}", _0)]
     NewClientFailed(String),
+    #[error("server is busy, status: {}", _0)]
+    ServerBusy(String),
+    #[error("server is not ready: {}", _0)]
+    ServerNotReady(String),
+    #[error("task {} timed out", _0)]
+    TaskTimeOut(String),
+    #[error("rpc failed with error: {}", _0)]
+    RpcFailed(String),
+    #[error("panicked: {}", _0)]
+    Panicked(String),
+    #[error("prove worker failed: {}", _0)]
+    ProveWorkerFailed(String),
+    #[error("insufficient memory: {}", _0)]
+    InsufficientMemory(String),
 }
 
+/// a caught panic payload, which is the panic message unless panicked with a custom value
 impl From<Box<dyn Any + Send>> for Error {
     fn from(inner: Box<dyn Any + Send>) -> Error {
-        Error::Unclassified(format!("{:?}", dbg!(inner)))
+        match inner.downcast::<String>() {
+            Ok(msg) => Error::Panicked(*msg),
+            Err(inner) => match inner.downcast::<&str>() {
+                Ok(msg) => Error::Panicked(msg.to_string()),
+                Err(inner) => Error::Panicked(format!("{:?}", inner)),
+            },
+        }
     }
 }
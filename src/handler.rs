@@ -0,0 +1,100 @@
+use crate::cpu::CpuSet;
+use crate::porep::SealCommitPhase2Handler;
+use crate::post::PoStHandler;
+use crate::server::ServerInfo;
+use crate::status::ProofType;
+use crate::tasks::TaskInfo;
+use crate::timings::PhaseTimings;
+use anyhow::{anyhow, Result};
+use std::sync::{Arc, Mutex, MutexGuard};
+use tracing::error;
+
+/// Proves one kind of task, `tasks::run_task` picks the handler by the task's proof type
+pub trait SnarkTaskHandler: Send + Sync {
+    /// prove the task and return the snark proof bytes handed back to the client
+    fn prove(&self, task_info: TaskInfo, ctx: &TaskContext) -> Result<Vec<u8>>;
+
+    /// sector size of the task, which picks its max prove time
+    fn sector_size(&self, task_info: &TaskInfo) -> Result<u64>;
+
+    /// partitions in memory at once while proving, which the memory estimate is based on
+    fn partitions_at_once(&self, task_info: &TaskInfo, partition_workers: usize) -> Result<usize>;
+}
+
+/// a new kind of task only needs a `ProofType` and a handler here
+pub fn handler_of(proof_type: ProofType) -> Box<dyn SnarkTaskHandler> {
+    match proof_type {
+        ProofType::WindowPoSt | ProofType::WinningPoSt => Box::new(PoStHandler),
+        ProofType::SealCommitPhase2 => Box::new(SealCommitPhase2Handler),
+    }
+}
+
+/// What a handler sees of the server while proving, and where it reports progress to
+#[derive(Debug, Clone)]
+pub struct TaskContext {
+    srv_info: Arc<Mutex<ServerInfo>>,
+    // `ServerInfo::task_generation` of the task proved, a timed out proof never writes into a later one
+    generation: u64,
+}
+
+impl TaskContext {
+    pub fn new(srv_info: Arc<Mutex<ServerInfo>>, generation: u64) -> Self {
+        TaskContext {
+            srv_info,
+            generation,
+        }
+    }
+
+    // the server info while it is still on the task of this context
+    fn current(&self) -> Option<MutexGuard<'_, ServerInfo>> {
+        match self.srv_info.lock() {
+            Ok(si) if si.task_generation == self.generation => Some(si),
+            Ok(_) => None,
+            Err(e) => {
+                error!("get lock failed with error: {}", e);
+                None
+            }
+        }
+    }
+
+    pub fn partition_workers(&self) -> Result<usize> {
+        match self.srv_info.lock() {
+            Ok(si) => Ok(si.partition_workers),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    pub fn cpu_set_of_slot(&self, slot: usize) -> Result<CpuSet> {
+        match self.srv_info.lock() {
+            Ok(si) => Ok(si.cpu_set_of_slot(slot)),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    pub fn set_partitions(&self, partitions: usize) {
+        if let Some(mut si) = self.current() {
+            si.task_info.partitions = partitions;
+            si.task_info.partitions_done = 0;
+        }
+    }
+
+    pub fn add_timings(&self, timings: &PhaseTimings) {
+        if let Some(mut si) = self.current() {
+            si.task_info.timings.add(timings);
+        }
+    }
+
+    /// phase timings of the task so far
+    pub fn timings(&self) -> Result<PhaseTimings> {
+        match self.srv_info.lock() {
+            Ok(si) => Ok(si.task_info.timings),
+            Err(e) => Err(anyhow!(e.to_string())),
+        }
+    }
+
+    pub fn add_partitions_done(&self, done: usize) {
+        if let Some(mut si) = self.current() {
+            si.task_info.partitions_done += done;
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::server::ServerInfo;
+use crate::snark_proof_grpc;
+use crate::status::TaskStatus;
+use crate::tasks;
+use crate::timings::PhaseTimings;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{error, info, warn};
+
+pub const TASK_HISTORY_LEN_DEFAULT: usize = 1000;
+pub const TASK_HISTORY_FILE: &str = "task_history.jsonl";
+
+/// What is left of a task once it ended
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TaskRecord {
+    pub task_id: String,
+    // miner id the client sent, or its address
+    pub client: String,
+    pub proof_type: String,
+    pub sector_size: u64,
+    pub partitions: usize,
+    pub submitted_at: SystemTime,
+    pub started_at: SystemTime,
+    pub ended_at: SystemTime,
+    // Done or Failed
+    pub status: TaskStatus,
+    pub error: String,
+    pub duration: Duration,
+    pub timings: PhaseTimings,
+}
+
+impl TaskRecord {
+    pub fn to_proto(&self) -> snark_proof_grpc::TaskRecord {
+        snark_proof_grpc::TaskRecord {
+            task_id: self.task_id.clone(),
+            client: self.client.clone(),
+            proof_type: self.proof_type.clone(),
+            sector_size: self.sector_size,
+            partitions: self.partitions as u32,
+            submitted_at: unix_millis(self.submitted_at),
+            started_at: unix_millis(self.started_at),
+            ended_at: unix_millis(self.ended_at),
+            status: self.status.to_string(),
+            error: self.error.clone(),
+            duration_ms: self.duration.as_millis() as u64,
+            timings: Some(self.timings.to_proto()),
+        }
+    }
+}
+
+/// Which records `TaskHistory::list` returns, by submit time in `[since, until)`
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub status: Option<TaskStatus>,
+    pub since: Option<SystemTime>,
+    pub until: Option<SystemTime>,
+    // most recent ones first, none lists all
+    pub limit: Option<usize>,
+}
+
+impl TaskFilter {
+    fn matches(&self, r: &TaskRecord) -> bool {
+        self.status.as_ref().map_or(true, |s| *s == r.status)
+            && self.since.map_or(true, |t| r.submitted_at >= t)
+            && self.until.map_or(true, |t| r.submitted_at < t)
+    }
+}
+
+/// The most recent tasks, appended to a file in the data dir if there is one
+#[derive(Debug)]
+pub struct TaskHistory {
+    records: VecDeque<TaskRecord>,
+    capacity: usize,
+    path: Option<PathBuf>,
+    // records in the file, which is rewritten with the kept ones once it has too many
+    lines: usize,
+}
+
+impl Default for TaskHistory {
+    fn default() -> Self {
+        TaskHistory::new(TASK_HISTORY_LEN_DEFAULT)
+    }
+}
+
+impl TaskHistory {
+    pub fn new(capacity: usize) -> Self {
+        TaskHistory {
+            records: VecDeque::new(),
+            capacity,
+            path: None,
+            lines: 0,
+        }
+    }
+
+    /// load the history saved in `data_dir`, later records are saved there too,
+    /// lines that can not be parsed are skipped
+    pub fn open(data_dir: &Path, capacity: usize) -> Result<Self> {
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(TASK_HISTORY_FILE);
+        let mut records = VecDeque::new();
+        let mut lines = 0;
+        if path.exists() {
+            for (n, line) in fs::read_to_string(&path)?.lines().enumerate() {
+                lines += 1;
+                match serde_json::from_str::<TaskRecord>(line) {
+                    Ok(r) => records.push_back(r),
+                    // like the last line of a crash while appending
+                    Err(e) => warn!("skip line {} of task history {:?}: {}", n + 1, path, e),
+                }
+            }
+        }
+        while records.len() > capacity {
+            records.pop_front();
+        }
+        info!("{} tasks loaded from history {:?}", records.len(), path);
+        let mut history = TaskHistory {
+            records,
+            capacity,
+            path: Some(path),
+            lines,
+        };
+        if history.lines > history.records.len() {
+            history.compact()?;
+        }
+        Ok(history)
+    }
+
+    /// keep `record`, appending one line to the file and rewriting it once it has too many
+    pub fn push(&mut self, record: TaskRecord) {
+        if let Err(e) = self.append(&record) {
+            error!("save task history failed with error: {}", e);
+        }
+        self.records.push_back(record);
+        while self.records.len() > self.capacity {
+            self.records.pop_front();
+        }
+        if self.lines > self.capacity.max(1) * 2 {
+            if let Err(e) = self.compact() {
+                error!("compact task history failed with error: {}", e);
+            }
+        }
+    }
+
+    pub fn list(&self, filter: &TaskFilter) -> Vec<TaskRecord> {
+        self.records
+            .iter()
+            .rev()
+            .filter(|r| filter.matches(r))
+            .take(filter.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    fn append(&mut self, record: &TaskRecord) -> Result<()> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let mut line = serde_json::to_vec(record)?;
+        line.push(b'\n');
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?
+            .write_all(&line)?;
+        self.lines += 1;
+        Ok(())
+    }
+
+    // the kept records only, written aside and renamed so a crash never leaves half a file
+    fn compact(&mut self) -> Result<()> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let mut content = vec![];
+        for r in &self.records {
+            content.extend(serde_json::to_vec(r)?);
+            content.push(b'\n');
+        }
+        let tmp = path.with_extension("jsonl.tmp");
+        fs::write(&tmp, content)?;
+        fs::rename(&tmp, path)?;
+        self.lines = self.records.len();
+        Ok(())
+    }
+}
+
+/// A record taken with the server locked, saved once the lock is released
+/// so rpcs do not wait on the history file
+#[must_use]
+pub struct PendingRecord {
+    history: Arc<Mutex<TaskHistory>>,
+    record: TaskRecord,
+}
+
+impl PendingRecord {
+    pub fn save(self) {
+        match self.history.lock() {
+            Ok(mut h) => h.push(self.record),
+            Err(e) => error!("get history lock failed with error: {}", e),
+        }
+    }
+}
+
+/// the record of the current task, once it is done or failed
+pub fn record_task(si: &ServerInfo) -> PendingRecord {
+    let t = &si.task_info;
+    let now = SystemTime::now();
+    let started_at = t.started_at.unwrap_or(now);
+    let record = TaskRecord {
+        task_id: t.task_id.clone(),
+        client: t.client.clone(),
+        proof_type: t.proof_type.clone(),
+        sector_size: tasks::sector_size_of(t).unwrap_or_default(),
+        partitions: t.partitions,
+        submitted_at: t.submitted_at.unwrap_or(started_at),
+        started_at,
+        ended_at: now,
+        status: t.task_status.clone(),
+        error: if t.task_status == TaskStatus::Failed {
+            si.error.clone()
+        } else {
+            String::default()
+        },
+        duration: now.duration_since(started_at).unwrap_or_default(),
+        timings: t.timings,
+    };
+    PendingRecord {
+        history: si.history.clone(),
+        record,
+    }
+}
+
+pub fn unix_millis(t: SystemTime) -> u64 {
+    t.duration_since(UNIX_EPOCH).unwrap_or_default().as_millis() as u64
+}
+
+/// 0 is no bound
+pub fn from_unix_millis(ms: u64) -> Option<SystemTime> {
+    if ms == 0 {
+        None
+    } else {
+        Some(UNIX_EPOCH + Duration::from_millis(ms))
+    }
+}
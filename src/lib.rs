@@ -1,8 +1,22 @@
+pub mod child;
 pub mod client;
+pub mod cpu;
 pub mod error;
+pub mod handler;
+pub mod history;
+pub mod listen;
+pub mod logging;
+pub mod memory;
+pub mod params;
+pub mod partition;
+pub mod porep;
+pub mod post;
 pub mod run;
+pub mod scheduler;
 pub mod server;
 pub mod snark_proof_grpc;
 pub mod status;
 pub mod tasks;
+pub mod timings;
+pub mod trace;
 pub mod utils;
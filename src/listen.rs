@@ -0,0 +1,102 @@
+use crate::error::Error;
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tonic::transport::server::Connected;
+
+pub const UNIX_SOCKET_PREFIX: &str = "unix:";
+
+/// Address the server listens on, parsed from strings like `0.0.0.0:50051`,
+/// `[::]:50051`, `10.0.0.5:50051` or `unix:/run/snark.sock`
+#[derive(Debug, PartialEq, Clone)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// listen on all IPv4 interfaces with the given port, the legacy `--port` behaviour
+    pub fn from_port(port: &str) -> Result<Self, Error> {
+        format!("0.0.0.0:{}", port).parse()
+    }
+}
+
+impl FromStr for ListenAddr {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix(UNIX_SOCKET_PREFIX) {
+            if path.is_empty() {
+                return Err(Error::InvalidParameters(format!(
+                    "empty unix socket path in listen address: {}",
+                    s
+                )));
+            }
+            Ok(ListenAddr::Unix(PathBuf::from(path)))
+        } else {
+            match s.parse::<SocketAddr>() {
+                Ok(addr) => Ok(ListenAddr::Tcp(addr)),
+                Err(e) => Err(Error::InvalidParameters(format!(
+                    "invalid listen address {}: {}",
+                    s, e
+                ))),
+            }
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(path) => write!(f, "{}{}", UNIX_SOCKET_PREFIX, path.display()),
+        }
+    }
+}
+
+/// tonic needs `Connected` on accepted streams, which it does not implement for unix sockets
+#[derive(Debug)]
+pub struct UnixStream(pub tokio::net::UnixStream);
+
+impl Connected for UnixStream {
+    type ConnectInfo = Option<PathBuf>;
+
+    fn connect_info(&self) -> Self::ConnectInfo {
+        self.0
+            .peer_addr()
+            .ok()
+            .and_then(|a| a.as_pathname().map(|p| p.to_path_buf()))
+    }
+}
+
+impl AsyncRead for UnixStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
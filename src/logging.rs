@@ -0,0 +1,83 @@
+use crate::trace;
+use opentelemetry_sdk::trace::Tracer;
+use std::env;
+use tracing::Subscriber;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+/// set to `json` for json formatted logs, child processes inherit it with the environment
+pub const LOG_FORMAT_ENV: &str = "WDPOST_LOG_FORMAT";
+/// the OTLP endpoint spans are exported to, child processes inherit it with the environment
+pub const OTLP_ENDPOINT_ENV: &str = "WDPOST_OTLP_ENDPOINT";
+
+/// log through `tracing`, with the fields of the current spans on every line,
+/// records of the `log` crate from dependencies are logged the same way,
+/// spans are exported over OTLP too when there is an endpoint
+pub fn init(json: bool, otlp_endpoint: Option<&str>) {
+    if json {
+        env::set_var(LOG_FORMAT_ENV, "json");
+    }
+    let tracer = otlp_endpoint.and_then(|endpoint| {
+        env::set_var(OTLP_ENDPOINT_ENV, endpoint);
+        match trace::otlp_tracer(endpoint, &service_name()) {
+            Ok(tracer) => Some(tracer),
+            Err(e) => {
+                eprintln!("init otlp export to {} failed with error: {}", endpoint, e);
+                None
+            }
+        }
+    });
+    if let Err(e) = subscriber(json, std::io::stderr, tracer).try_init() {
+        eprintln!("init logging failed with error: {}", e);
+    }
+}
+
+/// like `init`, with the format and OTLP endpoint the parent process chose
+pub fn init_from_env() {
+    init(
+        env::var(LOG_FORMAT_ENV).map_or(false, |f| f == "json"),
+        env::var(OTLP_ENDPOINT_ENV).ok().as_deref(),
+    )
+}
+
+/// the subscriber `init` installs, writing to `writer`, filtered by `RUST_LOG`,
+/// and exporting spans through `tracer` if any
+pub fn subscriber<W>(
+    json: bool,
+    writer: W,
+    tracer: Option<Tracer>,
+) -> Box<dyn Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info"));
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(writer);
+    if json {
+        Box::new(
+            builder
+                .json()
+                .with_current_span(true)
+                .with_span_list(true)
+                .finish()
+                .with(tracer.map(|t| tracing_opentelemetry::layer().with_tracer(t))),
+        )
+    } else {
+        Box::new(
+            builder
+                .finish()
+                .with(tracer.map(|t| tracing_opentelemetry::layer().with_tracer(t))),
+        )
+    }
+}
+
+// the binary name, the prove worker child reports as the server it runs for
+fn service_name() -> String {
+    env::current_exe()
+        .ok()
+        .and_then(|p| p.file_stem().map(|s| s.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| "window-post-snark-server".to_string())
+}
@@ -0,0 +1,106 @@
+use crate::error::Error;
+use crate::handler;
+use crate::server::ServerInfo;
+use crate::tasks::{get_proof_type, TaskInfo};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::fs;
+
+/// Peak memory of a proof of one sector size, which grows with the partitions proved at once
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MemoryEstimate {
+    pub base: u64,
+    pub per_partition: u64,
+}
+
+impl MemoryEstimate {
+    pub fn peak(&self, partitions: usize) -> u64 {
+        self.base + self.per_partition * partitions as u64
+    }
+}
+
+/// refuse a task whose estimated peak memory is more than the memory available now,
+/// tasks of sector sizes not in the estimates are always admitted
+pub fn check_memory(si: &ServerInfo, t: &TaskInfo) -> Result<()> {
+    if si.memory_estimates.is_empty() {
+        return Ok(());
+    }
+    let handler = handler::handler_of(get_proof_type(&t.proof_type)?);
+    let sector_size = handler.sector_size(t)?;
+    let estimate = match si.memory_estimates.get(&sector_size) {
+        Some(e) => e,
+        None => return Ok(()),
+    };
+    let partitions = handler.partitions_at_once(t, si.partition_workers)?;
+    let need = estimate.peak(partitions);
+    let available = mem_available()?;
+    if need > available {
+        return Err(anyhow::Error::from(Error::InsufficientMemory(format!(
+            "{} partitions of sector size {} need {} bytes, {} bytes available",
+            partitions, sector_size, need, available
+        ))));
+    }
+    Ok(())
+}
+
+/// memory available for new processes without swapping, as the kernel estimates it
+pub fn mem_available() -> Result<u64> {
+    let meminfo = fs::read_to_string("/proc/meminfo")?;
+    match parse_mem_available(&meminfo) {
+        Some(n) => Ok(n),
+        None => Err(anyhow::Error::msg("no MemAvailable in /proc/meminfo")),
+    }
+}
+
+/// MemAvailable of /proc/meminfo in bytes
+pub fn parse_mem_available(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find_map(|l| l.strip_prefix("MemAvailable:"))
+        .and_then(|v| v.trim().strip_suffix("kB"))
+        .and_then(|kb| kb.trim().parse::<u64>().ok())
+        .map(|kb| kb << 10)
+}
+
+/// parse memory like `512MiB`, `12GiB` or plain bytes
+pub fn parse_bytes(s: &str) -> Result<u64, Error> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let shift = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "kib" | "k" => 10,
+        "mib" | "m" => 20,
+        "gib" | "g" => 30,
+        "tib" | "t" => 40,
+        _ => {
+            return Err(Error::InvalidParameters(format!(
+                "unknown memory unit: {}",
+                s
+            )))
+        }
+    };
+    match num.parse::<u64>() {
+        Ok(n) => Ok(n << shift),
+        Err(e) => Err(Error::InvalidParameters(format!(
+            "invalid memory {}: {}",
+            s, e
+        ))),
+    }
+}
+
+/// parse `BASE+PER_PARTITION` like `4GiB+12GiB`
+pub fn parse_memory_estimate(s: &str) -> Result<MemoryEstimate, Error> {
+    match s.split_once('+') {
+        Some((base, per_partition)) => Ok(MemoryEstimate {
+            base: parse_bytes(base)?,
+            per_partition: parse_bytes(per_partition)?,
+        }),
+        None => Err(Error::InvalidParameters(format!(
+            "memory estimate should be like 4GiB+12GiB: {}",
+            s
+        ))),
+    }
+}
@@ -0,0 +1,210 @@
+use crate::error::Error;
+use anyhow::Result;
+use blake2b_simd::Params as Blake2bParams;
+use filecoin_proofs::caches::get_post_params;
+use filecoin_proofs::parameters::window_post_public_params;
+use filecoin_proofs::{
+    with_shape, PoStConfig, PoStType, SectorSize, PUBLISHED_SECTOR_SIZES,
+    WINDOW_POST_CHALLENGE_COUNT, WINDOW_POST_SECTOR_COUNT, WINNING_POST_CHALLENGE_COUNT,
+    WINNING_POST_SECTOR_COUNT,
+};
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+use std::time::Instant;
+use storage_proofs_core::api_version::ApiVersion;
+use storage_proofs_core::merkle::MerkleTreeTrait;
+use storage_proofs_core::parameter_cache::{
+    parameter_cache_params_path, parameter_cache_verifying_key_path, parameter_id,
+    verifying_key_id, CacheableParameters, PARAMETERS,
+};
+use storage_proofs_post::fallback::{FallbackPoStCircuit, FallbackPoStCompound};
+use strum_macros::Display;
+use tracing::info;
+
+/// parse sector size like `2KiB`, `512MiB`, `32GiB` or plain bytes like `34359738368`
+pub fn parse_sector_size(s: &str) -> Result<u64, Error> {
+    let s = s.trim();
+    let (num, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, ""),
+    };
+    let shift = match unit.to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "kib" | "k" => 10,
+        "mib" | "m" => 20,
+        "gib" | "g" => 30,
+        _ => {
+            return Err(Error::InvalidParameters(format!(
+                "unknown sector size unit: {}",
+                s
+            )))
+        }
+    };
+    let size = match num.parse::<u64>() {
+        Ok(n) => n << shift,
+        Err(e) => {
+            return Err(Error::InvalidParameters(format!(
+                "invalid sector size {}: {}",
+                s, e
+            )))
+        }
+    };
+    if PUBLISHED_SECTOR_SIZES.contains(&size) {
+        Ok(size)
+    } else {
+        Err(Error::InvalidParameters(format!(
+            "unsupported sector size: {}",
+            s
+        )))
+    }
+}
+
+/// the window post config a miner would use for `sector_size`, enough to address its parameters
+pub fn window_post_config(sector_size: u64) -> PoStConfig {
+    PoStConfig {
+        sector_size: SectorSize(sector_size),
+        challenge_count: WINDOW_POST_CHALLENGE_COUNT,
+        sector_count: *WINDOW_POST_SECTOR_COUNT
+            .read()
+            .expect("WINDOW_POST_SECTOR_COUNT poisoned")
+            .get(&sector_size)
+            .expect("unknown sector size"),
+        typ: PoStType::Window,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    }
+}
+
+/// the winning post config a miner would use for `sector_size`
+pub fn winning_post_config(sector_size: u64) -> PoStConfig {
+    PoStConfig {
+        sector_size: SectorSize(sector_size),
+        challenge_count: WINNING_POST_CHALLENGE_COUNT,
+        sector_count: WINNING_POST_SECTOR_COUNT,
+        typ: PoStType::Winning,
+        priority: false,
+        api_version: ApiVersion::V1_1_0,
+    }
+}
+
+/// load window post groth parameters of every sector size into the in-memory cache used by
+/// `run_snark`, winning post parameters are left to the first task that needs them
+pub fn preload_params(sector_sizes: &[u64]) -> Result<()> {
+    for size in sector_sizes {
+        let start = Instant::now();
+        info!("start to preload params of sector size {}", size);
+        let config = window_post_config(*size);
+        with_shape!(*size, preload_post_params, &config)?;
+        info!(
+            "params of sector size {} preloaded in {:?}",
+            size,
+            Instant::now().duration_since(start)
+        );
+    }
+    Ok(())
+}
+
+fn preload_post_params<Tree: 'static + MerkleTreeTrait>(post_config: &PoStConfig) -> Result<()> {
+    get_post_params::<Tree>(post_config)?;
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Clone, Display)]
+pub enum ParamFileState {
+    #[strum(to_string = "Ok")]
+    Ok,
+    #[strum(to_string = "Missing")]
+    Missing,
+    #[strum(to_string = "Corrupted")]
+    Corrupted,
+    #[strum(to_string = "NotInManifest")]
+    NotInManifest,
+}
+
+#[derive(Debug, Clone)]
+pub struct ParamFileReport {
+    pub sector_size: u64,
+    pub path: PathBuf,
+    pub state: ParamFileState,
+    pub expected_digest: String,
+    pub digest: String,
+}
+
+/// sector sizes whose window post params are listed in the parameter manifest
+pub fn manifest_sector_sizes() -> Vec<u64> {
+    PUBLISHED_SECTOR_SIZES
+        .iter()
+        .copied()
+        .filter(
+            |size| match with_shape!(*size, window_post_cache_id, &window_post_config(*size)) {
+                Ok(id) => PARAMETERS.contains_key(&parameter_id(&id)),
+                Err(_) => false,
+            },
+        )
+        .collect()
+}
+
+/// check window post `.params` and `.vk` files of every sector size against the parameter manifest
+pub fn verify_params(sector_sizes: &[u64]) -> Result<Vec<ParamFileReport>> {
+    let mut reports = vec![];
+    for size in sector_sizes {
+        let id = with_shape!(*size, window_post_cache_id, &window_post_config(*size))?;
+        reports.push(verify_param_file(
+            *size,
+            parameter_cache_params_path(&id),
+            &parameter_id(&id),
+        )?);
+        reports.push(verify_param_file(
+            *size,
+            parameter_cache_verifying_key_path(&id),
+            &verifying_key_id(&id),
+        )?);
+    }
+    Ok(reports)
+}
+
+fn verify_param_file(
+    sector_size: u64,
+    path: PathBuf,
+    manifest_id: &str,
+) -> Result<ParamFileReport> {
+    let mut report = ParamFileReport {
+        sector_size,
+        path,
+        state: ParamFileState::Ok,
+        expected_digest: String::default(),
+        digest: String::default(),
+    };
+    match PARAMETERS.get(manifest_id) {
+        Some(data) => report.expected_digest = data.digest.clone(),
+        None => {
+            report.state = ParamFileState::NotInManifest;
+            return Ok(report);
+        }
+    }
+    if !report.path.exists() {
+        report.state = ParamFileState::Missing;
+        return Ok(report);
+    }
+    info!("start to verify {:?}", report.path);
+    let mut file = File::open(&report.path)?;
+    let mut hasher = Blake2bParams::new().to_state();
+    io::copy(&mut file, &mut hasher)?;
+    // the first 32 hex characters (128 bits) of the blake2b-512 digest, as in parameters.json
+    report.digest = hasher.finalize().to_hex()[..32].to_string();
+    if report.digest != report.expected_digest {
+        report.state = ParamFileState::Corrupted;
+    }
+    Ok(report)
+}
+
+fn window_post_cache_id<Tree: 'static + MerkleTreeTrait>(
+    post_config: &PoStConfig,
+) -> Result<String> {
+    let pub_params = window_post_public_params::<Tree>(post_config)?;
+    Ok(<FallbackPoStCompound<Tree> as CacheableParameters<
+        FallbackPoStCircuit<Tree>,
+        _,
+    >>::cache_identifier(&pub_params))
+}
@@ -0,0 +1,83 @@
+use crate::error::Error;
+use crate::snark_proof_grpc::SnarkTaskRequestParams;
+use crate::tasks::get_post_config;
+use anyhow::Result;
+use filecoin_proofs::{get_partitions_for_window_post, PoStType};
+use serde_json::Value;
+
+/// Split a whole window post task into one task per partition, in partition order.
+///
+/// `vanilla_proof` holds one vanilla proof per partition and `pub_in.sectors` all sectors of
+/// the deadline, every partition task gets its own vanilla proof and chunk of sectors. Groth16
+/// proofs are randomized, so a partition proof is not the bytes the whole task would have
+/// produced at that index, but the merged proof has the same layout and verifies against the
+/// same public inputs.
+pub fn split_window_post_task(
+    task: &SnarkTaskRequestParams,
+) -> Result<Vec<SnarkTaskRequestParams>> {
+    let post_config = get_post_config(&task.post_config)?;
+    if post_config.typ != PoStType::Window {
+        return Err(invalid("only window post tasks have partitions"));
+    }
+    let partitions =
+        get_partitions_for_window_post(task.replicas_len as usize, &post_config).unwrap_or(1);
+
+    let vanilla_proofs = match serde_json::from_slice::<Value>(&task.vanilla_proof)? {
+        Value::Array(proofs) => proofs,
+        _ => return Err(invalid("vanilla proof is not a list of partition proofs")),
+    };
+    if vanilla_proofs.len() != partitions {
+        return Err(invalid(&format!(
+            "{} partitions for {} replicas, but {} vanilla proofs",
+            partitions,
+            task.replicas_len,
+            vanilla_proofs.len()
+        )));
+    }
+
+    let pub_in = serde_json::from_slice::<Value>(&task.pub_in)?;
+    let sectors = match pub_in.get("sectors") {
+        Some(Value::Array(sectors)) => sectors.clone(),
+        _ => return Err(invalid("pub_in has no sectors")),
+    };
+    if sectors.len() != task.replicas_len as usize {
+        return Err(invalid(&format!(
+            "replicas_len is {}, but pub_in has {} sectors",
+            task.replicas_len,
+            sectors.len()
+        )));
+    }
+
+    let mut tasks = Vec::with_capacity(partitions);
+    for (k, (vanilla_proof, chunk)) in vanilla_proofs
+        .into_iter()
+        .zip(sectors.chunks(post_config.sector_count))
+        .enumerate()
+    {
+        let mut partition_pub_in = pub_in.clone();
+        partition_pub_in["sectors"] = Value::Array(chunk.to_vec());
+        partition_pub_in["k"] = Value::from(k);
+        tasks.push(SnarkTaskRequestParams {
+            task_id: partition_task_id(&task.task_id, k),
+            vanilla_proof: serde_json::to_vec(&vec![vanilla_proof])?,
+            pub_in: serde_json::to_vec(&partition_pub_in)?,
+            post_config: task.post_config.clone(),
+            replicas_len: chunk.len() as u32,
+            proof_type: task.proof_type.clone(),
+        });
+    }
+    Ok(tasks)
+}
+
+pub fn partition_task_id(task_id: &str, k: usize) -> String {
+    format!("{}-p{}", task_id, k)
+}
+
+/// the final proof is the partition proofs concatenated in partition order
+pub fn merge_partition_proofs(proofs: Vec<Vec<u8>>) -> Vec<u8> {
+    proofs.concat()
+}
+
+fn invalid(msg: &str) -> anyhow::Error {
+    anyhow::Error::from(Error::InvalidParameters(msg.to_string()))
+}
@@ -0,0 +1,103 @@
+use crate::error::Error;
+use crate::handler::{SnarkTaskHandler, TaskContext};
+use crate::tasks::TaskInfo;
+use crate::timings;
+use crate::timings::PhaseTimings;
+use anyhow::Result;
+use filecoin_proofs::{
+    seal_commit_phase2, with_shape, PoRepConfig, PoRepProofPartitions, ProverId,
+    SealCommitPhase1Output, SectorSize, POREP_PARTITIONS,
+};
+use serde::{Deserialize, Serialize};
+use storage_proofs_core::api_version::ApiVersion;
+use storage_proofs_core::merkle::MerkleTreeTrait;
+use storage_proofs_core::sector::SectorId;
+
+/// `pub_in` of a seal commit phase 2 task, its `vanilla_proof` is the json of `SealCommitPhase1Output`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealCommitPhase2Inputs {
+    pub sector_size: u64,
+    pub porep_id: [u8; 32],
+    pub api_version: ApiVersion,
+    pub prover_id: ProverId,
+    pub sector_id: u64,
+}
+
+impl SealCommitPhase2Inputs {
+    pub fn porep_config(&self) -> Result<PoRepConfig> {
+        let partitions = match POREP_PARTITIONS
+            .read()
+            .expect("POREP_PARTITIONS poisoned")
+            .get(&self.sector_size)
+        {
+            Some(p) => *p,
+            None => {
+                return Err(anyhow::Error::from(Error::InvalidParameters(format!(
+                    "unsupported sector size: {}",
+                    self.sector_size
+                ))))
+            }
+        };
+        Ok(PoRepConfig {
+            sector_size: SectorSize(self.sector_size),
+            partitions: PoRepProofPartitions(partitions),
+            porep_id: self.porep_id,
+            api_version: self.api_version,
+        })
+    }
+}
+
+/// Proves PoRep commit phase 2 (C2) tasks offloaded from sealing workers
+pub struct SealCommitPhase2Handler;
+
+impl SnarkTaskHandler for SealCommitPhase2Handler {
+    fn prove(&self, task_info: TaskInfo, ctx: &TaskContext) -> Result<Vec<u8>> {
+        let inputs = serde_json::from_slice::<SealCommitPhase2Inputs>(&task_info.pub_in)?;
+        let porep_config = inputs.porep_config()?;
+        let partitions = usize::from(porep_config.partitions);
+        ctx.set_partitions(partitions);
+        let (proof, timings) = ctx.cpu_set_of_slot(0)?.install(|| {
+            with_shape!(
+                inputs.sector_size,
+                run_seal_commit_phase2,
+                porep_config,
+                &task_info.vanilla_proof,
+                &inputs
+            )
+        })??;
+        ctx.add_timings(&timings);
+        ctx.add_partitions_done(partitions);
+        Ok(proof)
+    }
+
+    fn sector_size(&self, task_info: &TaskInfo) -> Result<u64> {
+        let inputs = serde_json::from_slice::<SealCommitPhase2Inputs>(&task_info.pub_in)?;
+        Ok(inputs.sector_size)
+    }
+
+    fn partitions_at_once(&self, task_info: &TaskInfo, _: usize) -> Result<usize> {
+        let inputs = serde_json::from_slice::<SealCommitPhase2Inputs>(&task_info.pub_in)?;
+        Ok(usize::from(inputs.porep_config()?.partitions))
+    }
+}
+
+// seal_commit_phase2 loads its params itself, so their load time is part of prove
+fn run_seal_commit_phase2<Tree: 'static + MerkleTreeTrait>(
+    porep_config: PoRepConfig,
+    phase1_output: &[u8],
+    inputs: &SealCommitPhase2Inputs,
+) -> Result<(Vec<u8>, PhaseTimings)> {
+    let mut timings = PhaseTimings::default();
+    let phase1_output = timings::timed(&mut timings.decode, || {
+        serde_json::from_slice::<SealCommitPhase1Output<Tree>>(phase1_output)
+    })?;
+    let output = timings::timed(&mut timings.prove, || {
+        seal_commit_phase2(
+            porep_config,
+            phase1_output,
+            inputs.prover_id,
+            SectorId::from(inputs.sector_id),
+        )
+    })?;
+    Ok((output.proof, timings))
+}
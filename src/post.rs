@@ -0,0 +1,213 @@
+use crate::error::Error;
+use crate::handler::{SnarkTaskHandler, TaskContext};
+use crate::partition;
+use crate::snark_proof_grpc::SnarkTaskRequestParams;
+use crate::tasks::{get_post_config, get_proof_type, proof_type_of, TaskInfo};
+use crate::timings;
+use crate::timings::PhaseTimings;
+use anyhow::Result;
+use filecoin_proofs::caches::get_post_params;
+use filecoin_proofs::parameters::{window_post_setup_params, winning_post_setup_params};
+use filecoin_proofs::{get_partitions_for_window_post, with_shape, PoStConfig, PoStType};
+use std::cmp::min;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use storage_proofs_core::{compound_proof, compound_proof::CompoundProof, merkle::MerkleTreeTrait};
+use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCompound};
+use tracing::{info, Span};
+
+/// Proves window and winning post tasks, `post_config` is the json of their `PoStConfig`
+pub struct PoStHandler;
+
+impl SnarkTaskHandler for PoStHandler {
+    fn prove(&self, task_info: TaskInfo, ctx: &TaskContext) -> Result<Vec<u8>> {
+        let post_config = get_post_config(&task_info.post_config)?;
+        with_shape!(
+            post_config.sector_size.0,
+            run_snark,
+            task_info,
+            post_config,
+            ctx
+        )
+    }
+
+    fn sector_size(&self, task_info: &TaskInfo) -> Result<u64> {
+        Ok(get_post_config(&task_info.post_config)?.sector_size.0)
+    }
+
+    fn partitions_at_once(&self, task_info: &TaskInfo, partition_workers: usize) -> Result<usize> {
+        let partitions = partitions_of(task_info, &get_post_config(&task_info.post_config)?);
+        if partition_workers <= 1 {
+            Ok(partitions)
+        } else {
+            Ok(min(partition_workers, partitions))
+        }
+    }
+}
+
+// winning post is always proved in one partition
+fn partitions_of(task_info: &TaskInfo, post_config: &PoStConfig) -> usize {
+    match post_config.typ {
+        PoStType::Window => {
+            get_partitions_for_window_post(task_info.replicas_len, post_config).unwrap_or(1)
+        }
+        PoStType::Winning => 1,
+    }
+}
+
+// partition index and its proof
+type PartitionProof = (usize, Vec<u8>);
+
+fn run_snark<Tree: 'static + MerkleTreeTrait>(
+    task_info: TaskInfo,
+    post_config: PoStConfig,
+    ctx: &TaskContext,
+) -> Result<Vec<u8>> {
+    let proof_type = get_proof_type(&task_info.proof_type)?;
+    if proof_type != proof_type_of(&post_config) {
+        return Err(anyhow::Error::from(Error::InvalidParameters(format!(
+            "proof type is {}, but post config is for {}",
+            proof_type,
+            proof_type_of(&post_config)
+        ))));
+    }
+    let partitions = partitions_of(&task_info, &post_config);
+    ctx.set_partitions(partitions);
+    let workers = ctx.partition_workers()?;
+
+    if partitions == 1 || workers <= 1 {
+        let (proof, timings) = ctx.cpu_set_of_slot(0)?.install(|| {
+            prove_snark::<Tree>(
+                &task_info.vanilla_proof,
+                &task_info.pub_in,
+                task_info.replicas_len,
+                &post_config,
+            )
+        })??;
+        ctx.add_timings(&timings);
+        ctx.add_partitions_done(partitions);
+        return Ok(proof);
+    }
+
+    let task_id = task_info.task_id;
+    let partition_tasks = partition::split_window_post_task(&SnarkTaskRequestParams {
+        task_id: task_id.clone(),
+        vanilla_proof: task_info.vanilla_proof,
+        pub_in: task_info.pub_in,
+        post_config: task_info.post_config,
+        replicas_len: task_info.replicas_len as u32,
+        proof_type: task_info.proof_type,
+    })?;
+    info!(
+        "task {} split into {} partitions on {} workers",
+        task_id,
+        partitions,
+        min(workers, partitions)
+    );
+
+    // every worker takes the next partition not taken yet, until all are proved or one failed
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let prove_partitions = || -> Result<Vec<PartitionProof>> {
+        let mut proved = vec![];
+        while !failed.load(Ordering::SeqCst) {
+            let k = next.fetch_add(1, Ordering::SeqCst);
+            let t = match partition_tasks.get(k) {
+                Some(t) => t,
+                None => break,
+            };
+            match prove_snark::<Tree>(
+                &t.vanilla_proof,
+                &t.pub_in,
+                t.replicas_len as usize,
+                &post_config,
+            ) {
+                Ok((proof, timings)) => {
+                    info!("partition {} of task {} done, {}", k, task_id, timings);
+                    ctx.add_timings(&timings);
+                    ctx.add_partitions_done(1);
+                    proved.push((k, proof));
+                }
+                Err(e) => {
+                    failed.store(true, Ordering::SeqCst);
+                    return Err(e);
+                }
+            }
+        }
+        Ok(proved)
+    };
+    // every worker is a slot with its own threads and cores
+    let worker = |slot: usize| -> Result<Vec<PartitionProof>> {
+        ctx.cpu_set_of_slot(slot)?.install(prove_partitions)?
+    };
+    let span = Span::current();
+    let results: Vec<Result<Vec<PartitionProof>>> = thread::scope(|s| {
+        let handles: Vec<_> = (0..min(workers, partitions))
+            .map(|slot| {
+                let span = span.clone();
+                s.spawn(move || span.in_scope(|| worker(slot)))
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| match h.join() {
+                Ok(r) => r,
+                Err(p) => Err(anyhow::Error::from(Error::from(p))),
+            })
+            .collect()
+    });
+
+    let mut proofs = vec![];
+    for r in results {
+        proofs.extend(r?);
+    }
+    proofs.sort_by_key(|(k, _)| *k);
+    Ok(partition::merge_partition_proofs(
+        proofs.into_iter().map(|(_, proof)| proof).collect(),
+    ))
+}
+
+// a single compound proof over all partitions of the vanilla proofs, its bytes are the
+// partition proofs in order, which is why partitions proved apart and merged verify the same way
+fn prove_snark<Tree: 'static + MerkleTreeTrait>(
+    vanilla_proof: &[u8],
+    pub_in: &[u8],
+    replicas_len: usize,
+    post_config: &PoStConfig,
+) -> Result<(Vec<u8>, PhaseTimings)> {
+    let mut timings = PhaseTimings::default();
+    let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
+        timings::timed(&mut timings.setup, || {
+            let setup_params = match post_config.typ {
+                PoStType::Window => compound_proof::SetupParams {
+                    vanilla_params: window_post_setup_params(post_config),
+                    partitions: get_partitions_for_window_post(replicas_len, post_config),
+                    priority: post_config.priority,
+                },
+                PoStType::Winning => compound_proof::SetupParams {
+                    vanilla_params: winning_post_setup_params(post_config)?,
+                    partitions: None,
+                    priority: post_config.priority,
+                },
+            };
+            FallbackPoStCompound::setup(&setup_params)
+        })?;
+    let (vanilla_v, pub_in_v) = timings::timed(&mut timings.decode, || -> Result<_> {
+        Ok((
+            serde_json::from_slice(vanilla_proof)?,
+            serde_json::from_slice(pub_in)?,
+        ))
+    })?;
+    let groth_params = timings::timed(&mut timings.params_load, || {
+        get_post_params::<Tree>(post_config)
+    })?;
+    let proof = timings::timed(&mut timings.prove, || {
+        FallbackPoStCompound::prove_with_vanilla_by_snark_server(
+            &pub_params,
+            pub_in_v,
+            vanilla_v,
+            &groth_params,
+        )
+    })?;
+    Ok((proof.to_vec()?, timings))
+}
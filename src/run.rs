@@ -1,23 +1,92 @@
+use crate::cpu::CpuSet;
+use crate::error::Error;
+use crate::history::{TaskHistory, TASK_HISTORY_LEN_DEFAULT};
+use crate::listen::ListenAddr;
+use crate::memory::MemoryEstimate;
+use crate::scheduler::{SnarkScheduler, BACKEND_STATUS_INTERVAL_DEFAULT};
 use crate::server::{
-    WindowPostSnarkServer, SERVER_EXIT_TIME_OUT_AFTER_TASK_DONE_DEFAULT,
-    SERVER_LOCK_TIME_OUT_DEFAULT, SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT,
+    ServerInfo, WindowPostSnarkServer, SERVER_EXIT_TIME_OUT_AFTER_TASK_DONE_DEFAULT,
+    SERVER_LOCK_TIME_OUT_DEFAULT, SERVER_PARTITION_WORKERS_DEFAULT,
+    SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT,
 };
-use crate::{server, tasks, utils};
+use crate::status::ShutdownPolicy;
+use crate::{params, scheduler, server, tasks, trace};
 use anyhow::Context;
-use log::{debug, error, info};
 use signal_hook::consts::TERM_SIGNALS;
 use signal_hook::flag;
+use std::collections::HashMap;
+use std::env;
+use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
+use std::{panic, process};
 use tokio::sync::{mpsc, oneshot};
+use tracing::{debug, error, info};
 
-pub fn run(
-    port: String,
-    server_lock_time_out: Duration,
-    server_task_get_back_time_out: Duration,
-    server_exit_time_out_after_task_done: Duration,
-) {
+/// What a server runs with, the default listens on port 50051 with the default time outs
+#[derive(Debug, Clone)]
+pub struct RunConfig {
+    pub listen_addrs: Vec<ListenAddr>,
+    pub server_lock_time_out: Duration,
+    pub server_task_get_back_time_out: Duration,
+    pub server_exit_time_out_after_task_done: Duration,
+    // params loaded before the server takes tasks
+    pub preload_sector_sizes: Vec<u64>,
+    pub partition_workers: usize,
+    pub prove_in_child: bool,
+    pub max_prove_time: Option<Duration>,
+    pub max_prove_time_by_sector_size: HashMap<u64, Duration>,
+    pub cpu_set: CpuSet,
+    pub slot_cpu_sets: Vec<CpuSet>,
+    pub memory_estimates: HashMap<u64, MemoryEstimate>,
+    // the task history is kept in memory only without one
+    pub data_dir: Option<PathBuf>,
+    pub task_history_len: usize,
+    pub admin_token: Option<String>,
+}
+
+impl Default for RunConfig {
+    fn default() -> Self {
+        RunConfig {
+            listen_addrs: vec![ListenAddr::Tcp(SocketAddr::from(([0, 0, 0, 0], 50051)))],
+            server_lock_time_out: SERVER_LOCK_TIME_OUT_DEFAULT,
+            server_task_get_back_time_out: SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT,
+            server_exit_time_out_after_task_done: SERVER_EXIT_TIME_OUT_AFTER_TASK_DONE_DEFAULT,
+            preload_sector_sizes: vec![],
+            partition_workers: SERVER_PARTITION_WORKERS_DEFAULT,
+            prove_in_child: false,
+            max_prove_time: None,
+            max_prove_time_by_sector_size: HashMap::new(),
+            cpu_set: CpuSet::default(),
+            slot_cpu_sets: vec![],
+            memory_estimates: HashMap::new(),
+            data_dir: None,
+            task_history_len: TASK_HISTORY_LEN_DEFAULT,
+            admin_token: None,
+        }
+    }
+}
+
+pub fn run(config: RunConfig) {
+    let RunConfig {
+        listen_addrs,
+        server_lock_time_out,
+        server_task_get_back_time_out,
+        server_exit_time_out_after_task_done,
+        preload_sector_sizes,
+        partition_workers,
+        prove_in_child,
+        max_prove_time,
+        max_prove_time_by_sector_size,
+        cpu_set,
+        slot_cpu_sets,
+        memory_estimates,
+        data_dir,
+        task_history_len,
+        admin_token,
+    } = config;
     let rt = tokio::runtime::Runtime::new()
         .with_context(|| "failed to build new runtime")
         .unwrap();
@@ -54,16 +123,79 @@ pub fn run(
         }
     };
 
+    sv.set_preload_sector_sizes(preload_sector_sizes.clone())
+        .unwrap();
+    sv.set_partition_workers(partition_workers).unwrap();
+    if prove_in_child {
+        let exe = env::current_exe()
+            .with_context(|| "failed to get path of current executable")
+            .unwrap();
+        info!("tasks will be proved in child processes of {:?}", exe);
+        sv.set_prove_worker_exe(Some(exe)).unwrap();
+    }
+
+    sv.set_max_prove_time(max_prove_time, max_prove_time_by_sector_size)
+        .unwrap();
+    sv.set_cpu_sets(cpu_set, slot_cpu_sets).unwrap();
+    sv.set_memory_estimates(memory_estimates).unwrap();
+    let history = match data_dir {
+        Some(dir) => {
+            match TaskHistory::open(&dir, task_history_len) {
+                Ok(history) => history,
+                Err(e) => {
+                    error!("open task history in {:?} failed with error: {}, it is kept in memory only", dir, e);
+                    TaskHistory::new(task_history_len)
+                }
+            }
+        }
+        None => TaskHistory::new(task_history_len),
+    };
+    sv.set_task_history(history).unwrap();
+    sv.set_admin_token(admin_token).unwrap();
+
     debug!("server_info:{:?}", sv.server_info);
 
     let sv_i = sv.server_info.clone();
+    let shutdown_si = sv_i.clone();
 
-    let sv_handle = rt.spawn(server::run_server(server_exit_rx, sv, port));
+    // a server listening on nothing exits rather than look up but unreachable
+    let listeners = match rt.block_on(server::bind(listen_addrs)) {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            error!("{}, server exits", e);
+            trace::shutdown();
+            process::exit(1)
+        }
+    };
+    let sv_handle = rt.spawn(server::serve(server_exit_rx, sv, listeners));
 
-    let task_handle = rt.spawn(tasks::run_task(task_exit_rx, run_task_rx, sv_i));
+    let task_handle = rt.spawn(tasks::run_task(task_exit_rx, run_task_rx, sv_i.clone()));
 
-    // listen exit signal
-    rt.block_on(listen_exit_signal());
+    // server answers as not ready until params are loaded
+    if !preload_sector_sizes.is_empty() {
+        rt.spawn_blocking(move || {
+            let preloaded = panic::catch_unwind(|| params::preload_params(&preload_sector_sizes))
+                .unwrap_or_else(|p| Err(anyhow::Error::from(Error::from(p))));
+            match preloaded {
+                Ok(_) => {
+                    info!("all params preloaded, server is ready");
+                    match sv_i.lock() {
+                        Ok(mut si) => si.ready = true,
+                        Err(e) => error!("get lock failed with error: {}", e),
+                    }
+                }
+                // the server would never be ready, exit rather than answer as still loading
+                Err(e) => {
+                    error!("preload params failed with error: {}, server exits", e);
+                    trace::shutdown();
+                    process::exit(1)
+                }
+            }
+        });
+    }
+
+    // listen exit signal, or a shutdown rpc
+    rt.block_on(listen_shutdown(shutdown_si));
 
     // stop task
     match task_exit_tx.send("exit".to_string()) {
@@ -103,24 +235,98 @@ pub fn run(
         }
     });
 
-    // del file lock
-    utils::del_file_lock();
     rt.shutdown_background();
     info!("server main process exited")
 }
 
+pub fn run_scheduler(listen_addrs: Vec<ListenAddr>, backends: Vec<String>) {
+    let rt = tokio::runtime::Runtime::new()
+        .with_context(|| "failed to build new runtime")
+        .unwrap();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (watch_exit_tx, watch_exit_rx) = oneshot::channel::<String>();
+
+    let sc = SnarkScheduler::new(backends);
+    let sc_i = sc.scheduler_info.clone();
+
+    let listeners = match rt.block_on(server::bind(listen_addrs)) {
+        Ok(listeners) => listeners,
+        Err(e) => {
+            error!("{}, scheduler exits", e);
+            trace::shutdown();
+            process::exit(1)
+        }
+    };
+    let sc_handle = rt.spawn(server::serve(server_exit_rx, sc, listeners));
+    let watch_handle = rt.spawn(scheduler::watch_backends(
+        watch_exit_rx,
+        sc_i,
+        BACKEND_STATUS_INTERVAL_DEFAULT,
+    ));
+
+    rt.block_on(listen_exit_signal());
+
+    if let Err(e) = watch_exit_tx.send("exit".to_string()) {
+        error!("{}", e);
+    }
+    if let Err(e) = server_exit_tx.send("exit".to_string()) {
+        error!("{}", e);
+    }
+    rt.block_on(async {
+        if let Err(e) = watch_handle.await {
+            error!("{}", e)
+        }
+        if let Err(e) = sc_handle.await {
+            error!("{}", e)
+        }
+    });
+    rt.shutdown_background();
+    info!("scheduler main process exited")
+}
+
 async fn listen_exit_signal() {
+    let term = match term_flag() {
+        Some(term) => term,
+        None => return,
+    };
+    while !term.load(Ordering::Relaxed) {
+        tokio::time::sleep(Duration::new(1, 0)).await;
+    }
+}
+
+// a TERM signal shuts down after the result is got back, a shutdown rpc with its own policy
+async fn listen_shutdown(srv_info: Arc<Mutex<ServerInfo>>) {
+    let term = match term_flag() {
+        Some(term) => term,
+        None => return,
+    };
+    loop {
+        match srv_info.lock() {
+            Ok(mut si) => {
+                if term.load(Ordering::Relaxed) && si.shutdown.is_none() {
+                    si.shutdown = Some(ShutdownPolicy::AfterResult);
+                }
+                if let Some(policy) = si.shutdown {
+                    info!("server will shut down, policy: {}", policy);
+                    return;
+                }
+            }
+            Err(e) => error!("get lock failed with error: {}", e),
+        }
+        tokio::time::sleep(Duration::new(1, 0)).await;
+    }
+}
+
+fn term_flag() -> Option<Arc<AtomicBool>> {
     let term = Arc::new(AtomicBool::new(false));
     for sig in TERM_SIGNALS {
         match flag::register(*sig, Arc::clone(&term)) {
             Ok(_) => {}
             Err(e) => {
                 error!("failed to register TERM_SIGNALS with error:{}", e);
-                return;
+                return None;
             }
         };
     }
-    while !term.load(Ordering::Relaxed) {
-        tokio::time::sleep(Duration::new(1, 0)).await;
-    }
+    Some(term)
 }
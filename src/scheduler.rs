@@ -0,0 +1,645 @@
+use crate::client::{new_client, SnarkClient, CLIENT_PROVE_TIME_OUT_DEFAULT};
+use crate::error;
+use crate::server::{
+    rpc_span, SERVER_LOCK_TIME_OUT_DEFAULT, SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT,
+};
+use crate::snark_proof_grpc::snark_task_service_server::SnarkTaskService;
+use crate::snark_proof_grpc::{
+    BaseResponse, GetServerStatusRequest, GetServerStatusResponse, GetTaskResultRequest,
+    GetTaskResultResponse, GetWorkerStatusRequest, ListTasksRequest, ListTasksResponse,
+    ShutdownRequest, ShutdownResponse, SnarkTaskRequestParams, UnlockServerRequest,
+};
+use crate::status::{ServerStatus, TaskStatus};
+use crate::{trace, utils};
+use opentelemetry::trace::FutureExt;
+use opentelemetry::Context;
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::select;
+use tokio::sync::oneshot;
+use tonic::{Request, Response, Status};
+use tracing::{error, info, info_span, warn, Instrument};
+
+pub const BACKEND_STATUS_INTERVAL_DEFAULT: Duration = Duration::from_secs(5);
+pub const BACKEND_RESULT_POLL_INTERVAL_DEFAULT: Duration = Duration::from_secs(2);
+pub const BACKEND_CLIENT_TIME_OUT_DEFAULT: Duration = Duration::from_secs(60);
+pub const TASK_MAX_ATTEMPTS_DEFAULT: usize = 3;
+
+/// Dispatches miners' tasks to a pool of snark servers, speaking the same `SnarkTaskService`
+#[derive(Debug)]
+pub struct SnarkScheduler {
+    pub scheduler_info: Arc<Mutex<SchedulerInfo>>,
+}
+
+#[derive(Debug)]
+pub struct SchedulerInfo {
+    pub backends: Vec<BackendInfo>,
+    pub tasks: HashMap<String, SchedulerTask>,
+    pub lock_time_out: Duration,
+    pub task_get_back_time_out: Duration,
+    pub result_poll_interval: Duration,
+    pub client_time_out: Duration,
+    // a backend not done with a task by then fails it, the task is tried on another one
+    pub prove_time_out: Duration,
+    pub max_attempts: usize,
+}
+
+/// last known state of a backend snark server
+#[derive(Debug, Clone)]
+pub struct BackendInfo {
+    pub addr: String,
+    pub status: ServerStatus,
+    pub ready: bool,
+    // task dispatched to this backend by the scheduler, empty if none
+    pub task_id: String,
+    pub failures: u64,
+    pub last_update_time: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct SchedulerTask {
+    pub task_id: String,
+    pub params: Option<SnarkTaskRequestParams>,
+    // None: locked by miner, Ready: waiting for a backend, Working: dispatched
+    pub task_status: TaskStatus,
+    pub backend: String,
+    pub attempts: usize,
+    pub result: Vec<u8>,
+    pub error: String,
+    pub last_update_time: Instant,
+}
+
+enum DispatchError {
+    // backend was taken by someone else before we got its lock
+    Busy(String),
+    // the task itself failed or was rejected, it would fail the same way on another backend
+    TaskFailed(String),
+    // backend unreachable, lost the task or ran out of time, the task is tried on another one
+    Failed(String),
+}
+
+impl BackendInfo {
+    pub fn new(addr: String) -> Self {
+        BackendInfo {
+            addr,
+            status: ServerStatus::Unknown,
+            ready: false,
+            task_id: String::default(),
+            failures: 0,
+            last_update_time: Instant::now(),
+        }
+    }
+
+    fn is_free(&self) -> bool {
+        self.status == ServerStatus::Free && self.ready && self.task_id.is_empty()
+    }
+}
+
+impl SchedulerTask {
+    fn new(task_id: String) -> Self {
+        SchedulerTask {
+            task_id,
+            params: None,
+            task_status: TaskStatus::None,
+            backend: String::default(),
+            attempts: 0,
+            result: vec![],
+            error: String::default(),
+            last_update_time: Instant::now(),
+        }
+    }
+}
+
+impl SchedulerInfo {
+    pub fn new(backends: Vec<String>) -> Self {
+        SchedulerInfo {
+            backends: backends.into_iter().map(BackendInfo::new).collect(),
+            tasks: HashMap::new(),
+            lock_time_out: SERVER_LOCK_TIME_OUT_DEFAULT,
+            task_get_back_time_out: SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT,
+            result_poll_interval: BACKEND_RESULT_POLL_INTERVAL_DEFAULT,
+            client_time_out: BACKEND_CLIENT_TIME_OUT_DEFAULT,
+            prove_time_out: CLIENT_PROVE_TIME_OUT_DEFAULT,
+            max_attempts: TASK_MAX_ATTEMPTS_DEFAULT,
+        }
+    }
+
+    // drop locks never followed by a task and results never fetched
+    fn drop_expired_tasks(&mut self) {
+        let now = Instant::now();
+        let (lock_time_out, get_back_time_out) = (self.lock_time_out, self.task_get_back_time_out);
+        self.tasks.retain(|_, t| match t.task_status {
+            TaskStatus::None => now.duration_since(t.last_update_time) <= lock_time_out,
+            TaskStatus::Done | TaskStatus::Failed => {
+                now.duration_since(t.last_update_time) < get_back_time_out
+            }
+            _ => true,
+        });
+    }
+
+    // free backends not yet promised to a locked or queued task
+    fn unreserved_backends(&self) -> usize {
+        let free = self.backends.iter().filter(|b| b.is_free()).count();
+        let reserved = self
+            .tasks
+            .values()
+            .filter(|t| t.task_status == TaskStatus::None || t.task_status == TaskStatus::Ready)
+            .count();
+        free.saturating_sub(reserved)
+    }
+
+    // prefer untried backends with fewer failures
+    fn pick_backend(&self, tried: &HashSet<String>) -> Option<usize> {
+        let candidates = || {
+            self.backends
+                .iter()
+                .enumerate()
+                .filter(|(_, b)| b.is_free())
+        };
+        candidates()
+            .filter(|(_, b)| !tried.contains(&b.addr))
+            .min_by_key(|(_, b)| b.failures)
+            .or_else(|| candidates().min_by_key(|(_, b)| b.failures))
+            .map(|(i, _)| i)
+    }
+}
+
+impl SnarkScheduler {
+    pub fn new(backends: Vec<String>) -> Self {
+        SnarkScheduler {
+            scheduler_info: Arc::new(Mutex::new(SchedulerInfo::new(backends))),
+        }
+    }
+
+    fn lock_server_if_free(&self, task_id: String) -> Result<ServerStatus, Status> {
+        let mut si = match self.scheduler_info.lock() {
+            Ok(s) => s,
+            Err(e) => return Err(Status::aborted(e.to_string())),
+        };
+        si.drop_expired_tasks();
+        if let Some(t) = si.tasks.get(&task_id) {
+            return if t.task_status == TaskStatus::None {
+                Ok(ServerStatus::Free)
+            } else {
+                Ok(ServerStatus::Working)
+            };
+        }
+        if si.unreserved_backends() > 0 {
+            si.tasks
+                .insert(task_id.clone(), SchedulerTask::new(task_id));
+            Ok(ServerStatus::Free)
+        } else if si.backends.iter().any(|b| b.ready) {
+            Ok(ServerStatus::Working)
+        } else {
+            Ok(ServerStatus::Unknown)
+        }
+    }
+
+    // `cx` is the trace context of the miner, the backends join its trace
+    fn do_task(&self, task_params: SnarkTaskRequestParams, cx: Context) -> Result<(), Status> {
+        let mut si = match self.scheduler_info.lock() {
+            Ok(s) => s,
+            Err(e) => return Err(Status::aborted(e.to_string())),
+        };
+        let task_id = task_params.task_id.clone();
+        match si.tasks.get_mut(&task_id) {
+            Some(t) if t.task_status == TaskStatus::None => {
+                t.params = Some(task_params);
+                t.task_status = TaskStatus::Ready;
+                t.last_update_time = Instant::now();
+            }
+            Some(_) => {
+                return Err(Status::cancelled(
+                    "task was already submitted, can not be submitted again",
+                ))
+            }
+            None => {
+                return Err(Status::cancelled(
+                    "server should be locked until task is executed",
+                ))
+            }
+        }
+        drop(si);
+        // requests to backends carry the context of this span when spans are exported,
+        // else the one of the miner as is
+        let span = info_span!("dispatch", task_id = %task_id);
+        tokio::spawn(
+            dispatch(self.scheduler_info.clone(), task_id)
+                .instrument(span)
+                .with_context(cx),
+        );
+        Ok(())
+    }
+
+    fn get_task_result(&self, task_id: String) -> Result<Vec<u8>, Status> {
+        let mut si = match self.scheduler_info.lock() {
+            Ok(s) => s,
+            Err(e) => return Err(Status::aborted(e.to_string())),
+        };
+        let task_status = match si.tasks.get(&task_id) {
+            Some(t) => t.task_status.clone(),
+            None => {
+                return Err(Status::invalid_argument(
+                    anyhow::Error::from(error::Error::InvalidParameters(format!(
+                        "no task {} on this scheduler",
+                        task_id
+                    )))
+                    .to_string(),
+                ))
+            }
+        };
+        match task_status {
+            TaskStatus::Done => Ok(si.tasks.remove(&task_id).unwrap().result),
+            TaskStatus::Failed => {
+                let t = si.tasks.remove(&task_id).unwrap();
+                Err(Status::aborted(
+                    anyhow::Error::from(error::Error::TaskFailedWithError(t.error)).to_string(),
+                ))
+            }
+            TaskStatus::None => Err(Status::cancelled(
+                anyhow::Error::from(error::Error::NoTaskRunningOnSever).to_string(),
+            )),
+            _ => Ok(vec![]),
+        }
+    }
+
+    fn unlock(&self, task_id: String) -> Result<(), Status> {
+        let mut si = match self.scheduler_info.lock() {
+            Ok(s) => s,
+            Err(e) => return Err(Status::aborted(e.to_string())),
+        };
+        match si.tasks.get(&task_id) {
+            Some(t) if t.task_status == TaskStatus::None => {
+                si.tasks.remove(&task_id);
+                Ok(())
+            }
+            Some(_) => Err(Status::cancelled(
+                "this operation just used to unlock a server in status Locked",
+            )),
+            None => Err(Status::cancelled("server is already Free")),
+        }
+    }
+
+    fn get_server_status(&self) -> Result<GetServerStatusResponse, Status> {
+        let si = match self.scheduler_info.lock() {
+            Ok(s) => s,
+            Err(e) => return Err(Status::aborted(e.to_string())),
+        };
+        let status = if si.unreserved_backends() > 0 {
+            ServerStatus::Free
+        } else if si.backends.iter().any(|b| b.ready) {
+            ServerStatus::Working
+        } else {
+            ServerStatus::Unknown
+        };
+        Ok(GetServerStatusResponse {
+            status: status.to_string(),
+            ready: si.backends.iter().any(|b| b.ready),
+            version: utils::version().to_string(),
+            preload_sector_sizes: vec![],
+            tasks_timed_out: 0,
+            // tasks and time outs are the backends' own
+            ..GetServerStatusResponse::default()
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl SnarkTaskService for SnarkScheduler {
+    async fn do_snark_task(
+        &self,
+        request: Request<SnarkTaskRequestParams>,
+    ) -> Result<Response<BaseResponse>, Status> {
+        let span = rpc_span("DoSnarkTask", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
+        let cx = trace::extract(request.metadata());
+        match self.do_task(request.into_inner(), cx) {
+            Ok(_) => Ok(Response::new(BaseResponse {
+                msg: "ok".to_string(),
+            })),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn lock_server_if_free(
+        &self,
+        request: Request<GetWorkerStatusRequest>,
+    ) -> Result<Response<BaseResponse>, Status> {
+        let span = rpc_span("LockServerIfFree", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
+        match self.lock_server_if_free(request.into_inner().task_id) {
+            Ok(s) => Ok(Response::new(BaseResponse { msg: s.to_string() })),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_snark_task_result(
+        &self,
+        request: Request<GetTaskResultRequest>,
+    ) -> Result<Response<GetTaskResultResponse>, Status> {
+        let span = rpc_span("GetSnarkTaskResult", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
+        match self.get_task_result(request.into_inner().task_id) {
+            Ok(v) => {
+                if !v.is_empty() {
+                    Ok(Response::new(GetTaskResultResponse {
+                        msg: "ok".to_string(),
+                        result: v,
+                        partitions: 0,
+                        partitions_done: 0,
+                        timings: None,
+                    }))
+                } else {
+                    Ok(Response::new(GetTaskResultResponse {
+                        msg: TaskStatus::Working.to_string(),
+                        result: v,
+                        partitions: 0,
+                        partitions_done: 0,
+                        timings: None,
+                    }))
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn unlock_server(
+        &self,
+        request: Request<UnlockServerRequest>,
+    ) -> Result<Response<BaseResponse>, Status> {
+        let span = rpc_span("UnlockServer", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
+        match self.unlock(request.into_inner().task_id) {
+            Ok(_) => Ok(Response::new(BaseResponse {
+                msg: "ok".to_string(),
+            })),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn get_server_status(
+        &self,
+        request: Request<GetServerStatusRequest>,
+    ) -> Result<Response<GetServerStatusResponse>, Status> {
+        let span = rpc_span("GetServerStatus", "", &request);
+        let _enter = span.enter();
+        match self.get_server_status() {
+            Ok(s) => Ok(Response::new(s)),
+            Err(e) => Err(e),
+        }
+    }
+
+    // task history is kept by each backend server
+    async fn list_tasks(
+        &self,
+        _: Request<ListTasksRequest>,
+    ) -> Result<Response<ListTasksResponse>, Status> {
+        Err(Status::unimplemented(
+            "scheduler keeps no task history, list tasks of its backends",
+        ))
+    }
+
+    // each backend server is shut down on its own
+    async fn shutdown(
+        &self,
+        _: Request<ShutdownRequest>,
+    ) -> Result<Response<ShutdownResponse>, Status> {
+        Err(Status::unimplemented(
+            "scheduler is stopped by a signal, shut down its backends one by one",
+        ))
+    }
+}
+
+// run a submitted task on free backends until it succeeds or runs out of attempts
+async fn dispatch(sc_info: Arc<Mutex<SchedulerInfo>>, task_id: String) {
+    let mut tried = HashSet::new();
+    loop {
+        let (picked, poll_interval, client_time_out, prove_time_out) = {
+            let mut si = match sc_info.lock() {
+                Ok(s) => s,
+                Err(e) => {
+                    error!("get lock failed with error: {}", e);
+                    return;
+                }
+            };
+            let (attempts, params) = match si.tasks.get(&task_id) {
+                Some(t) => (t.attempts, t.params.clone()),
+                None => {
+                    warn!("task {} dropped before dispatched", task_id);
+                    return;
+                }
+            };
+            if attempts >= si.max_attempts {
+                let t = si.tasks.get_mut(&task_id).unwrap();
+                error!("task {} failed after {} attempts", task_id, attempts);
+                t.task_status = TaskStatus::Failed;
+                t.last_update_time = Instant::now();
+                return;
+            }
+            let picked = match si.pick_backend(&tried) {
+                Some(i) => {
+                    let backend = &mut si.backends[i];
+                    backend.task_id = task_id.clone();
+                    backend.status = ServerStatus::Locked;
+                    backend.last_update_time = Instant::now();
+                    let addr = backend.addr.clone();
+                    let t = si.tasks.get_mut(&task_id).unwrap();
+                    t.task_status = TaskStatus::Working;
+                    t.backend = addr.clone();
+                    t.attempts += 1;
+                    t.last_update_time = Instant::now();
+                    Some((addr, params.unwrap()))
+                }
+                None => None,
+            };
+            (
+                picked,
+                si.result_poll_interval,
+                si.client_time_out,
+                si.prove_time_out,
+            )
+        };
+        let (addr, params) = match picked {
+            Some(p) => p,
+            None => {
+                // all backends are busy, wait for one to be free
+                tokio::time::sleep(poll_interval).await;
+                continue;
+            }
+        };
+
+        info!("dispatch task {} to backend {}", task_id, addr);
+        let result = run_on_backend(
+            &addr,
+            params,
+            poll_interval,
+            client_time_out,
+            prove_time_out,
+        )
+        .await;
+
+        let mut si = match sc_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                error!("get lock failed with error: {}", e);
+                return;
+            }
+        };
+        if let Some(b) = si.backends.iter_mut().find(|b| b.addr == addr) {
+            b.task_id = String::default();
+            b.last_update_time = Instant::now();
+            match &result {
+                Ok(_) | Err(DispatchError::TaskFailed(_)) => {
+                    b.status = ServerStatus::Free;
+                }
+                Err(DispatchError::Busy(status)) => {
+                    b.status = ServerStatus::from_str(status).unwrap_or(ServerStatus::Unknown);
+                }
+                Err(DispatchError::Failed(_)) => {
+                    b.status = ServerStatus::Unknown;
+                    b.ready = false;
+                    b.failures += 1;
+                }
+            }
+        }
+        let t = match si.tasks.get_mut(&task_id) {
+            Some(t) => t,
+            None => return,
+        };
+        t.last_update_time = Instant::now();
+        match result {
+            Ok(r) => {
+                info!("task {} done on backend {}", task_id, addr);
+                t.result = r;
+                t.task_status = TaskStatus::Done;
+                return;
+            }
+            Err(DispatchError::TaskFailed(e)) => {
+                error!(
+                    "task {} failed on backend {} with error: {}",
+                    task_id, addr, e
+                );
+                t.error = e;
+                t.task_status = TaskStatus::Failed;
+                return;
+            }
+            Err(DispatchError::Busy(status)) => {
+                // not counted as an attempt, the task never ran there
+                t.attempts -= 1;
+                t.task_status = TaskStatus::Ready;
+                info!("backend {} is {}, pick another one", addr, status);
+            }
+            Err(DispatchError::Failed(e)) => {
+                warn!(
+                    "task {} failed on backend {} with error: {}",
+                    task_id, addr, e
+                );
+                t.error = e;
+                t.task_status = TaskStatus::Ready;
+                tried.insert(addr);
+            }
+        }
+    }
+}
+
+async fn run_on_backend(
+    addr: &str,
+    params: SnarkTaskRequestParams,
+    poll_interval: Duration,
+    client_time_out: Duration,
+    prove_time_out: Duration,
+) -> Result<Vec<u8>, DispatchError> {
+    let task_id = params.task_id.clone();
+    let failed = |e: anyhow::Error| match e.downcast_ref::<error::Error>() {
+        Some(error::Error::TaskFailedWithError(msg)) => DispatchError::TaskFailed(msg.clone()),
+        Some(error::Error::InvalidParameters(_)) => DispatchError::TaskFailed(e.to_string()),
+        _ => DispatchError::Failed(e.to_string()),
+    };
+    let mut client = SnarkClient::connect(addr, client_time_out)
+        .await
+        .map_err(failed)?;
+    client.set_poll_interval(poll_interval, poll_interval);
+    client.set_prove_time_out(prove_time_out);
+    match client.try_lock(&task_id).await {
+        Ok(ServerStatus::Free) => {}
+        Ok(s) => return Err(DispatchError::Busy(s.to_string())),
+        Err(e) => return Err(failed(e)),
+    }
+    client.submit(params).await.map_err(failed)?;
+    client
+        .wait_result(&task_id, Instant::now())
+        .await
+        .map_err(failed)
+}
+
+/// refresh status of backends not running a task of this scheduler
+pub async fn watch_backends(
+    exit_rx: oneshot::Receiver<String>,
+    sc_info: Arc<Mutex<SchedulerInfo>>,
+    interval: Duration,
+) {
+    info!("backend watcher run");
+    let watching = async {
+        loop {
+            let (addrs, client_time_out) = match sc_info.lock() {
+                Ok(si) => (
+                    si.backends
+                        .iter()
+                        .filter(|b| b.task_id.is_empty())
+                        .map(|b| b.addr.clone())
+                        .collect::<Vec<_>>(),
+                    si.client_time_out,
+                ),
+                Err(e) => {
+                    error!("get lock failed with error: {}", e);
+                    return;
+                }
+            };
+            for addr in addrs {
+                let status = match new_client(&addr, client_time_out).await {
+                    Ok(mut c) => c
+                        .get_server_status(Request::new(GetServerStatusRequest {}))
+                        .await
+                        .map(|r| r.into_inner())
+                        .map_err(|s| s.message().to_string()),
+                    Err(e) => Err(e.to_string()),
+                };
+                let mut si = match sc_info.lock() {
+                    Ok(s) => s,
+                    Err(e) => {
+                        error!("get lock failed with error: {}", e);
+                        return;
+                    }
+                };
+                let b = match si.backends.iter_mut().find(|b| b.addr == addr) {
+                    // a task may have been dispatched to it while we were asking
+                    Some(b) if b.task_id.is_empty() => b,
+                    _ => continue,
+                };
+                match status {
+                    Ok(s) => {
+                        b.status =
+                            ServerStatus::from_str(&s.status).unwrap_or(ServerStatus::Unknown);
+                        b.ready = s.ready;
+                    }
+                    Err(e) => {
+                        if b.status != ServerStatus::Unknown {
+                            warn!("backend {} is unreachable: {}", addr, e);
+                        }
+                        b.status = ServerStatus::Unknown;
+                        b.ready = false;
+                    }
+                }
+                b.last_update_time = Instant::now();
+            }
+            tokio::time::sleep(interval).await;
+        }
+    };
+    select! {
+        _ = exit_rx => {}
+        _ = watching => {}
+    }
+    info!("backend watcher exited");
+}
@@ -1,27 +1,51 @@
+use crate::cpu::CpuSet;
 use crate::error;
+use crate::history;
+use crate::history::{TaskFilter, TaskHistory};
+use crate::listen;
+use crate::listen::ListenAddr;
+use crate::memory;
+use crate::memory::MemoryEstimate;
 use crate::snark_proof_grpc::snark_task_service_server::{
     SnarkTaskService, SnarkTaskServiceServer,
 };
 use crate::snark_proof_grpc::{
-    BaseResponse, GetTaskResultRequest, GetTaskResultResponse, GetWorkerStatusRequest,
-    SnarkTaskRequestParams, UnlockServerRequest,
+    BaseResponse, GetServerStatusRequest, GetServerStatusResponse, GetTaskResultRequest,
+    GetTaskResultResponse, GetWorkerStatusRequest, ListTasksRequest, ListTasksResponse,
+    ShutdownRequest, ShutdownResponse, SnarkTaskRequestParams, UnlockServerRequest,
 };
-use crate::status::{ServerStatus, TaskStatus};
+use crate::status::{ServerStatus, ShutdownPolicy, TaskStatus};
 use crate::tasks;
 use crate::tasks::{set_task_info, TaskInfo};
-use futures::FutureExt;
-use log::info;
-use std::net::SocketAddr;
+use crate::trace;
+use crate::utils;
+use futures::future::join_all;
+use futures::{FutureExt, StreamExt};
+use std::collections::HashMap;
+use std::fs::remove_file;
+use std::future::Future;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::net::{TcpListener, UnixListener};
 use tokio::sync::mpsc::UnboundedSender;
 use tokio::sync::oneshot;
+use tokio_stream::wrappers::{TcpListenerStream, UnixListenerStream};
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
+use tracing::{error, field, info, info_span, warn, Span};
 
 pub const SERVER_LOCK_TIME_OUT_DEFAULT: Duration = Duration::from_secs(10);
 pub const SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT: Duration = Duration::from_secs(60);
 pub const SERVER_EXIT_TIME_OUT_AFTER_TASK_DONE_DEFAULT: Duration = Duration::from_secs(300);
+pub const SERVER_PARTITION_WORKERS_DEFAULT: usize = 1;
+/// metadata key of the miner id a client may send along its requests
+pub const MINER_ID_METADATA_KEY: &str = "miner-id";
+/// metadata key of the token admin requests like `Shutdown` carry
+pub const ADMIN_TOKEN_METADATA_KEY: &str = "admin-token";
+pub const SERVER_NOT_READY_MSG: &str = "server is not ready, params are still loading";
 
 #[derive(Debug)]
 pub struct WindowPostSnarkServer {
@@ -38,6 +62,34 @@ pub struct ServerInfo {
     pub server_task_get_back_time_out: Duration,
     pub server_exit_time_out_after_task_done: Duration,
     pub error: String,
+    // false until preloaded params are in memory
+    pub ready: bool,
+    pub preload_sector_sizes: Vec<u64>,
+    // partitions of one task proved in parallel, 1 proves the whole task in a single call
+    pub partition_workers: usize,
+    // prove every task in a child process of this executable instead of in the server process
+    pub prove_worker_exe: Option<PathBuf>,
+    pub prove_worker_pid: Option<u32>,
+    // a task proving longer than this is failed, none proves without a limit
+    pub max_prove_time: Option<Duration>,
+    // overrides max_prove_time for tasks of these sector sizes
+    pub max_prove_time_by_sector_size: HashMap<u64, Duration>,
+    pub tasks_timed_out: u64,
+    // threads and cores of a proof, per partition worker by index if slot sets are given
+    pub cpu_set: CpuSet,
+    pub slot_cpu_sets: Vec<CpuSet>,
+    // peak memory by sector size, a task that does not fit in available memory is refused
+    pub memory_estimates: HashMap<u64, MemoryEstimate>,
+    // ended tasks, most recent last
+    pub history: Arc<Mutex<TaskHistory>>,
+    // required by admin rpcs, without one they are only served to local clients
+    pub admin_token: Option<String>,
+    // set once a shutdown is asked for, no task is accepted after it
+    pub shutdown: Option<ShutdownPolicy>,
+    // bumped when a task starts proving, what the proof of an earlier task writes is dropped
+    pub task_generation: u64,
+    // a timed out proof still running in this process, no task is accepted until it ends
+    pub orphaned_proof: bool,
 }
 
 impl Default for ServerInfo {
@@ -50,6 +102,49 @@ impl Default for ServerInfo {
             server_task_get_back_time_out: SERVER_TASK_GET_BACK_TIME_OUT_DEFAULT,
             server_exit_time_out_after_task_done: SERVER_EXIT_TIME_OUT_AFTER_TASK_DONE_DEFAULT,
             error: String::default(),
+            ready: true,
+            preload_sector_sizes: vec![],
+            partition_workers: SERVER_PARTITION_WORKERS_DEFAULT,
+            prove_worker_exe: None,
+            prove_worker_pid: None,
+            max_prove_time: None,
+            max_prove_time_by_sector_size: HashMap::new(),
+            tasks_timed_out: 0,
+            cpu_set: CpuSet::default(),
+            slot_cpu_sets: vec![],
+            memory_estimates: HashMap::new(),
+            history: Arc::new(Mutex::new(TaskHistory::default())),
+            admin_token: None,
+            shutdown: None,
+            task_generation: 0,
+            orphaned_proof: false,
+        }
+    }
+}
+
+impl ServerInfo {
+    /// the longest a task of `sector_size` may prove, unknown sizes get the global limit
+    pub fn max_prove_time_of(&self, sector_size: Option<u64>) -> Option<Duration> {
+        sector_size
+            .and_then(|size| self.max_prove_time_by_sector_size.get(&size).copied())
+            .or(self.max_prove_time)
+    }
+
+    /// the status once the task is over, a server still running a timed out proof takes no task
+    pub fn idle_status(&self) -> ServerStatus {
+        if self.orphaned_proof {
+            ServerStatus::Unknown
+        } else {
+            ServerStatus::Free
+        }
+    }
+
+    /// the cpu set of partition worker `slot`, slot sets are reused when there are more workers
+    pub fn cpu_set_of_slot(&self, slot: usize) -> CpuSet {
+        if self.slot_cpu_sets.is_empty() {
+            self.cpu_set.clone()
+        } else {
+            self.slot_cpu_sets[slot % self.slot_cpu_sets.len()].clone()
         }
     }
 }
@@ -116,7 +211,106 @@ impl WindowPostSnarkServer {
         Ok(())
     }
 
-    fn do_task(&self, task_params: &SnarkTaskRequestParams) -> Result<(), Status> {
+    /// the server is not ready until the params of these sector sizes are loaded
+    pub fn set_preload_sector_sizes(&self, sector_sizes: Vec<u64>) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.ready = sector_sizes.is_empty();
+        si.preload_sector_sizes = sector_sizes;
+        Ok(())
+    }
+
+    pub fn set_partition_workers(&self, partition_workers: usize) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.partition_workers = partition_workers;
+        Ok(())
+    }
+
+    pub fn set_prove_worker_exe(&self, exe: Option<PathBuf>) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.prove_worker_exe = exe;
+        Ok(())
+    }
+
+    pub fn set_max_prove_time(
+        &self,
+        time_out: Option<Duration>,
+        by_sector_size: HashMap<u64, Duration>,
+    ) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.max_prove_time = time_out;
+        si.max_prove_time_by_sector_size = by_sector_size;
+        Ok(())
+    }
+
+    pub fn set_cpu_sets(&self, cpu_set: CpuSet, slot_cpu_sets: Vec<CpuSet>) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.cpu_set = cpu_set;
+        si.slot_cpu_sets = slot_cpu_sets;
+        Ok(())
+    }
+
+    pub fn set_memory_estimates(
+        &self,
+        memory_estimates: HashMap<u64, MemoryEstimate>,
+    ) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.memory_estimates = memory_estimates;
+        Ok(())
+    }
+
+    pub fn set_task_history(&self, history: TaskHistory) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.history = Arc::new(Mutex::new(history));
+        Ok(())
+    }
+
+    pub fn set_admin_token(&self, admin_token: Option<String>) -> anyhow::Result<()> {
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(anyhow::Error::msg(e.to_string()));
+            }
+        };
+        si.admin_token = admin_token;
+        Ok(())
+    }
+
+    fn do_task(&self, task_params: &SnarkTaskRequestParams, client: String) -> Result<(), Status> {
         let mut si = match self.server_info.lock() {
             Ok(s) => s,
             Err(e) => {
@@ -127,7 +321,23 @@ impl WindowPostSnarkServer {
         let task_id = task_params.task_id.clone();
         if si.status == ServerStatus::Locked && si.task_info.task_id == task_id {
             // set task info
-            let task_info = set_task_info(task_params);
+            let mut task_info = set_task_info(task_params);
+            task_info.client = client;
+            task_info.trace_context = trace::to_headers(&trace::current_context());
+            if let Some(size) = tasks::sector_size_of(&task_info) {
+                Span::current().record("sector_size", size);
+            }
+            // the server stays locked, the client unlocks it and tries another one,
+            // a task the check fails on for other reasons fails when proved
+            if let Err(e) = memory::check_memory(&si, &task_info) {
+                match e.downcast_ref::<error::Error>() {
+                    Some(error::Error::InsufficientMemory(_)) => {
+                        error!("task {} refused: {}", task_id, e);
+                        return Err(Status::resource_exhausted(e.to_string()));
+                    }
+                    _ => warn!("check memory of task {} failed with error: {}", task_id, e),
+                }
+            }
             // set server info
             si.task_info = task_info;
             si.status = ServerStatus::Working;
@@ -159,6 +369,12 @@ impl WindowPostSnarkServer {
             Ok(s) => s,
             Err(e) => return Err(Status::aborted(e.to_string())),
         };
+        if !si.ready {
+            return Err(Status::unavailable(SERVER_NOT_READY_MSG));
+        }
+        if si.shutdown.is_some() || si.orphaned_proof {
+            return Ok(ServerStatus::Unknown);
+        }
         match si.status {
             ServerStatus::Free => {
                 si.task_info = TaskInfo::default();
@@ -202,7 +418,7 @@ impl WindowPostSnarkServer {
         }
     }
 
-    fn get_task_result(&self, task_id: String) -> Result<Vec<u8>, Status> {
+    fn get_task_result(&self, task_id: String) -> Result<GetTaskResultResponse, Status> {
         let mut si = match self.server_info.lock() {
             Ok(s) => s,
             Err(e) => {
@@ -221,19 +437,31 @@ impl WindowPostSnarkServer {
                 ))
             } else {
                 if si.task_info.task_status == TaskStatus::Done {
-                    si.status = ServerStatus::Free;
+                    si.status = si.idle_status();
                     si.last_update_time = Instant::now();
                     si.task_info.task_status = TaskStatus::Returned;
-                    Ok(si.task_info.result.clone())
+                    Ok(GetTaskResultResponse {
+                        msg: "ok".to_string(),
+                        result: si.task_info.result.clone(),
+                        partitions: si.task_info.partitions as u32,
+                        partitions_done: si.task_info.partitions_done as u32,
+                        timings: Some(si.task_info.timings.to_proto()),
+                    })
                 } else if si.task_info.task_status == TaskStatus::Failed {
-                    si.status = ServerStatus::Free;
+                    si.status = si.idle_status();
                     si.last_update_time = Instant::now();
                     Err(Status::aborted(
                         anyhow::Error::from(error::Error::TaskFailedWithError(si.error.clone()))
                             .to_string(),
                     ))
                 } else {
-                    Ok(vec![])
+                    Ok(GetTaskResultResponse {
+                        msg: TaskStatus::Working.to_string(),
+                        result: vec![],
+                        partitions: si.task_info.partitions as u32,
+                        partitions_done: si.task_info.partitions_done as u32,
+                        timings: None,
+                    })
                 }
             }
         } else {
@@ -243,6 +471,107 @@ impl WindowPostSnarkServer {
         }
     }
 
+    fn get_server_status(&self) -> Result<GetServerStatusResponse, Status> {
+        let si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(Status::aborted(e.to_string()));
+            }
+        };
+        Ok(GetServerStatusResponse {
+            status: si.status.to_string(),
+            ready: si.ready,
+            version: utils::version().to_string(),
+            preload_sector_sizes: si.preload_sector_sizes.clone(),
+            tasks_timed_out: si.tasks_timed_out,
+            task_id: si.task_info.task_id.clone(),
+            task_status: si.task_info.task_status.to_string(),
+            in_state_ms: si.last_update_time.elapsed().as_millis() as u64,
+            lock_time_out_ms: si.server_lock_time_out.as_millis() as u64,
+            task_get_back_time_out_ms: si.server_task_get_back_time_out.as_millis() as u64,
+            exit_time_out_after_task_done_ms: si.server_exit_time_out_after_task_done.as_millis()
+                as u64,
+            max_prove_time_ms: si.max_prove_time.unwrap_or_default().as_millis() as u64,
+            max_prove_time_ms_by_sector_size: si
+                .max_prove_time_by_sector_size
+                .iter()
+                .map(|(size, t)| (*size, t.as_millis() as u64))
+                .collect(),
+            shutdown: si.shutdown.map(|p| p.to_string()).unwrap_or_default(),
+        })
+    }
+
+    fn list_tasks(&self, req: ListTasksRequest) -> Result<ListTasksResponse, Status> {
+        let status = if req.status.is_empty() {
+            None
+        } else {
+            match TaskStatus::from_str(&req.status) {
+                Ok(s) => Some(s),
+                Err(_) => {
+                    return Err(Status::invalid_argument(format!(
+                        "unknown task status: {}",
+                        req.status
+                    )))
+                }
+            }
+        };
+        let filter = TaskFilter {
+            status,
+            since: history::from_unix_millis(req.since),
+            until: history::from_unix_millis(req.until),
+            limit: if req.limit == 0 {
+                None
+            } else {
+                Some(req.limit as usize)
+            },
+        };
+        // the history has a lock of its own, it may be busy writing its file
+        let history = match self.server_info.lock() {
+            Ok(si) => si.history.clone(),
+            Err(e) => {
+                return Err(Status::aborted(e.to_string()));
+            }
+        };
+        let history = match history.lock() {
+            Ok(h) => h,
+            Err(e) => {
+                return Err(Status::aborted(e.to_string()));
+            }
+        };
+        Ok(ListTasksResponse {
+            tasks: history.list(&filter).iter().map(|r| r.to_proto()).collect(),
+        })
+    }
+
+    fn shutdown<T>(&self, request: &Request<T>, policy: &str) -> Result<ShutdownResponse, Status> {
+        let policy = match ShutdownPolicy::from_str(policy) {
+            Ok(p) => p,
+            Err(_) => {
+                return Err(Status::invalid_argument(format!(
+                    "unknown shutdown policy: {}",
+                    policy
+                )))
+            }
+        };
+        let mut si = match self.server_info.lock() {
+            Ok(s) => s,
+            Err(e) => {
+                return Err(Status::aborted(e.to_string()));
+            }
+        };
+        check_admin(si.admin_token.as_deref(), request)?;
+        // a later request may only make it sooner
+        if si.shutdown.map_or(true, |p| policy < p) {
+            warn!("shutdown {} asked by {}", policy, client_of(request));
+            si.shutdown = Some(policy);
+        }
+        Ok(ShutdownResponse {
+            status: si.status.to_string(),
+            task_id: si.task_info.task_id.clone(),
+            task_status: si.task_info.task_status.to_string(),
+        })
+    }
+
     fn unlock(&self, task_id: String) -> Result<(), Status> {
         let mut si = match self.server_info.lock() {
             Ok(s) => s,
@@ -280,9 +609,12 @@ impl SnarkTaskService for WindowPostSnarkServer {
         &self,
         request: Request<SnarkTaskRequestParams>,
     ) -> Result<Response<BaseResponse>, Status> {
+        let span = rpc_span("DoSnarkTask", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
         // get all params
+        let client = client_of(&request);
         let params_all = request.into_inner();
-        match self.do_task(&params_all) {
+        match self.do_task(&params_all, client) {
             Ok(_) => Ok({
                 Response::new(BaseResponse {
                     msg: "ok".to_string(),
@@ -296,6 +628,8 @@ impl SnarkTaskService for WindowPostSnarkServer {
         &self,
         request: Request<GetWorkerStatusRequest>,
     ) -> Result<Response<BaseResponse>, Status> {
+        let span = rpc_span("LockServerIfFree", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
         match self.lock_server_if_free(request.into_inner().task_id) {
             Ok(s) => Ok(Response::new(BaseResponse { msg: s.to_string() })),
             Err(e) => Err(e),
@@ -306,20 +640,10 @@ impl SnarkTaskService for WindowPostSnarkServer {
         &self,
         request: Request<GetTaskResultRequest>,
     ) -> Result<Response<GetTaskResultResponse>, Status> {
+        let span = rpc_span("GetSnarkTaskResult", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
         match self.get_task_result(request.into_inner().task_id) {
-            Ok(v) => {
-                if v.len() > 0 {
-                    Ok(Response::new(GetTaskResultResponse {
-                        msg: "ok".to_string(),
-                        result: v,
-                    }))
-                } else {
-                    Ok(Response::new(GetTaskResultResponse {
-                        msg: TaskStatus::Working.to_string(),
-                        result: v,
-                    }))
-                }
-            }
+            Ok(r) => Ok(Response::new(r)),
             Err(e) => Err(e),
         }
     }
@@ -328,6 +652,8 @@ impl SnarkTaskService for WindowPostSnarkServer {
         &self,
         request: Request<UnlockServerRequest>,
     ) -> Result<Response<BaseResponse>, Status> {
+        let span = rpc_span("UnlockServer", &request.get_ref().task_id, &request);
+        let _enter = span.enter();
         match self.unlock(request.into_inner().task_id) {
             Ok(_) => Ok(Response::new(BaseResponse {
                 msg: "ok".to_string(),
@@ -335,22 +661,224 @@ impl SnarkTaskService for WindowPostSnarkServer {
             Err(e) => Err(e),
         }
     }
+
+    async fn get_server_status(
+        &self,
+        request: Request<GetServerStatusRequest>,
+    ) -> Result<Response<GetServerStatusResponse>, Status> {
+        let span = rpc_span("GetServerStatus", "", &request);
+        let _enter = span.enter();
+        match self.get_server_status() {
+            Ok(s) => Ok(Response::new(s)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn list_tasks(
+        &self,
+        request: Request<ListTasksRequest>,
+    ) -> Result<Response<ListTasksResponse>, Status> {
+        let span = rpc_span("ListTasks", "", &request);
+        let _enter = span.enter();
+        match self.list_tasks(request.into_inner()) {
+            Ok(r) => Ok(Response::new(r)),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn shutdown(
+        &self,
+        request: Request<ShutdownRequest>,
+    ) -> Result<Response<ShutdownResponse>, Status> {
+        let span = rpc_span("Shutdown", "", &request);
+        let _enter = span.enter();
+        match self.shutdown(&request, &request.get_ref().policy) {
+            Ok(r) => Ok(Response::new(r)),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+// the admin token if the server has one, else a client on this host
+fn check_admin<T>(admin_token: Option<&str>, request: &Request<T>) -> Result<(), Status> {
+    match admin_token {
+        Some(token) => {
+            let sent = request
+                .metadata()
+                .get(ADMIN_TOKEN_METADATA_KEY)
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default();
+            // compared in constant time
+            let differs = sent.len() != token.len()
+                || sent
+                    .bytes()
+                    .zip(token.bytes())
+                    .fold(0, |acc, (a, b)| acc | (a ^ b))
+                    != 0;
+            if differs {
+                return Err(Status::unauthenticated("invalid admin token"));
+            }
+            Ok(())
+        }
+        // unix socket clients have no address
+        None => match request.remote_addr() {
+            Some(addr) if !addr.ip().is_loopback() => Err(Status::permission_denied(
+                "admin requests from other hosts need the server to have an admin token",
+            )),
+            _ => Ok(()),
+        },
+    }
+}
+
+// the sector size is recorded once the task is known,
+// the span joins the trace the client sent along the request
+pub(crate) fn rpc_span<T>(method: &str, task_id: &str, request: &Request<T>) -> Span {
+    let span = info_span!(
+        "rpc",
+        method,
+        task_id,
+        miner_id = %client_of(request),
+        sector_size = field::Empty
+    );
+    trace::set_parent(&span, trace::extract(request.metadata()));
+    span
+}
+
+// the miner id in the request metadata, or the address the request came from
+fn client_of<T>(request: &Request<T>) -> String {
+    if let Some(id) = request
+        .metadata()
+        .get(MINER_ID_METADATA_KEY)
+        .and_then(|v| v.to_str().ok())
+    {
+        return id.to_string();
+    }
+    match request.remote_addr() {
+        Some(addr) => addr.to_string(),
+        None => "unix".to_string(),
+    }
+}
+
+/// bind every listen address and serve on them until `srv_exit_rx`, fails if none of them
+/// could be bound
+pub async fn run_server<T: SnarkTaskService>(
+    srv_exit_rx: oneshot::Receiver<String>,
+    srv: T,
+    listen_addrs: Vec<ListenAddr>,
+) -> anyhow::Result<()> {
+    let listeners = bind(listen_addrs).await?;
+    serve(srv_exit_rx, srv, listeners).await;
+    Ok(())
+}
+
+/// a listen address bound, not served yet
+pub struct Listener {
+    addr: ListenAddr,
+    bound: Bound,
+}
+
+enum Bound {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+/// bind the listen addresses, one that fails is logged and left out, it is an error only if
+/// none of them could be bound, so a server never runs listening on nothing
+pub async fn bind(listen_addrs: Vec<ListenAddr>) -> anyhow::Result<Vec<Listener>> {
+    let mut listeners = vec![];
+    let mut errors = vec![];
+    for addr in listen_addrs {
+        match bind_on(&addr).await {
+            Ok(bound) => listeners.push(Listener { addr, bound }),
+            Err(e) => {
+                error!("listen on {} failed with error: {}", addr, e);
+                errors.push(format!("{}: {}", addr, e));
+            }
+        }
+    }
+    if listeners.is_empty() {
+        return Err(anyhow::Error::msg(format!(
+            "no listen address could be bound: {}",
+            errors.join(", ")
+        )));
+    }
+    Ok(listeners)
+}
+
+async fn bind_on(addr: &ListenAddr) -> std::io::Result<Bound> {
+    match addr {
+        ListenAddr::Tcp(a) => Ok(Bound::Tcp(TcpListener::bind(a).await?)),
+        ListenAddr::Unix(path) => {
+            if is_stale_socket(path) {
+                if let Err(e) = remove_file(path) {
+                    error!("remove stale socket {:?} failed with error: {}", path, e);
+                }
+            }
+            Ok(Bound::Unix(UnixListener::bind(path)?))
+        }
+    }
 }
 
-pub async fn run_server(
+/// serve on bound listeners until `srv_exit_rx`
+pub async fn serve<T: SnarkTaskService>(
     srv_exit_rx: oneshot::Receiver<String>,
-    srv: WindowPostSnarkServer,
-    port: String,
+    srv: T,
+    listeners: Vec<Listener>,
 ) {
-    let mut addr_s = "0.0.0.0:".to_string();
-    addr_s += &port;
-    let addr = addr_s.parse::<SocketAddr>().unwrap();
-    info!("Server listening on {}", addr);
-    Server::builder()
-        .accept_http1(true)
-        .add_service(SnarkTaskServiceServer::new(srv))
-        .serve_with_shutdown(addr, srv_exit_rx.map(drop))
-        .await
-        .unwrap();
+    let svc = SnarkTaskServiceServer::new(srv);
+    // every listener stops on the same exit signal
+    let shutdown = srv_exit_rx.map(drop).shared();
+    let served = listeners
+        .into_iter()
+        .map(|l| serve_on(l, svc.clone(), shutdown.clone()));
+    join_all(served).await;
     info!("server stop listen")
 }
+
+async fn serve_on<T: SnarkTaskService, F: Future<Output = ()>>(
+    listener: Listener,
+    svc: SnarkTaskServiceServer<T>,
+    shutdown: F,
+) {
+    let addr = listener.addr;
+    info!("Server listening on {}", addr);
+    // a listener that fails is logged, the others keep serving
+    let served = match listener.bound {
+        Bound::Tcp(tcp) => {
+            Server::builder()
+                .accept_http1(true)
+                .add_service(svc)
+                .serve_with_incoming_shutdown(TcpListenerStream::new(tcp), shutdown)
+                .await
+        }
+        Bound::Unix(uds) => {
+            let incoming = UnixListenerStream::new(uds).map(|s| s.map(listen::UnixStream));
+            let served = Server::builder()
+                .accept_http1(true)
+                .add_service(svc)
+                .serve_with_incoming_shutdown(incoming, shutdown)
+                .await;
+            if let ListenAddr::Unix(path) = &addr {
+                if let Err(e) = remove_file(path) {
+                    error!("remove socket {:?} failed with error: {}", path, e);
+                }
+            }
+            served
+        }
+    };
+    if let Err(e) = served {
+        error!("serve on {} failed with error: {}", addr, e);
+        return;
+    }
+    info!("server stop listen on {}", addr)
+}
+// neither other files nor the socket of a server still listening
+fn is_stale_socket(path: &Path) -> bool {
+    match std::fs::symlink_metadata(path) {
+        Ok(m) if m.file_type().is_socket() => match std::os::unix::net::UnixStream::connect(path) {
+            Err(e) => e.kind() == std::io::ErrorKind::ConnectionRefused,
+            Ok(_) => false,
+        },
+        _ => false,
+    }
+}
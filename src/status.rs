@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumString};
 
 #[derive(Debug, PartialEq, Clone, EnumString, Display)]
@@ -18,7 +19,7 @@ impl Default for ServerStatus {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, EnumString, Display)]
+#[derive(Debug, PartialEq, Clone, EnumString, Display, Serialize, Deserialize)]
 pub enum TaskStatus {
     #[strum(to_string = "None")]
     None,
@@ -39,3 +40,40 @@ impl Default for TaskStatus {
         TaskStatus::None
     }
 }
+
+#[derive(Debug, PartialEq, Clone, Copy, EnumString, Display)]
+pub enum ProofType {
+    #[strum(to_string = "WindowPoSt")]
+    WindowPoSt,
+    #[strum(to_string = "WinningPoSt")]
+    WinningPoSt,
+    #[strum(to_string = "SealCommitPhase2")]
+    SealCommitPhase2,
+}
+
+impl Default for ProofType {
+    fn default() -> Self {
+        ProofType::WindowPoSt
+    }
+}
+
+/// When a server asked to shut down exits, new tasks are refused meanwhile,
+/// policies are ordered from the soonest
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, EnumString, Display)]
+pub enum ShutdownPolicy {
+    // at once, a task still proving is abandoned
+    #[strum(to_string = "Immediate")]
+    Immediate,
+    // once the current task is proved, without waiting for the miner to get the result
+    #[strum(to_string = "AfterTask")]
+    AfterTask,
+    // once the miner got the result of the current task, as on SIGTERM
+    #[strum(to_string = "AfterResult")]
+    AfterResult,
+}
+
+impl Default for ShutdownPolicy {
+    fn default() -> Self {
+        ShutdownPolicy::AfterResult
+    }
+}
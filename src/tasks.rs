@@ -1,29 +1,47 @@
+use crate::child;
+use crate::error::Error;
+use crate::handler;
+use crate::handler::TaskContext;
+use crate::history;
 use crate::server::ServerInfo;
 use crate::snark_proof_grpc::SnarkTaskRequestParams;
-use crate::status::{ServerStatus, TaskStatus};
-use filecoin_proofs::caches::get_post_params;
-use filecoin_proofs::parameters::window_post_setup_params;
-use filecoin_proofs::{get_partitions_for_window_post, with_shape, PoStConfig};
-use log::{error, info, warn};
+use crate::status::{ProofType, ServerStatus, ShutdownPolicy, TaskStatus};
+use crate::timings::PhaseTimings;
+use crate::trace;
+use filecoin_proofs::{PoStConfig, PoStType};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::str::FromStr;
 use std::sync::{Arc, Mutex};
-use std::time::{Duration, Instant};
-use storage_proofs_core::{
-    compound_proof, compound_proof::CompoundProof, error::Result, merkle::MerkleTreeTrait,
-};
-use storage_proofs_post::fallback::{FallbackPoSt, FallbackPoStCompound};
+use std::time::{Duration, Instant, SystemTime};
+use storage_proofs_core::error::Result;
 use tokio::select;
 use tokio::sync::mpsc::UnboundedReceiver;
 use tokio::sync::oneshot;
+use tokio::task;
+use tracing::{error, info, info_span, warn, Span};
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
 pub struct TaskInfo {
     pub task_id: String,
     pub vanilla_proof: Vec<u8>,
     pub pub_in: Vec<u8>,
     pub post_config: Vec<u8>,
     pub replicas_len: usize,
+    pub proof_type: String,
     pub result: Vec<u8>,
     pub task_status: TaskStatus,
+    pub partitions: usize,
+    pub partitions_done: usize,
+    pub timings: PhaseTimings,
+    // miner id the client sent, or its address
+    pub client: String,
+    pub submitted_at: Option<SystemTime>,
+    pub started_at: Option<SystemTime>,
+    // trace context of the request that submitted the task, as w3c headers
+    pub trace_context: HashMap<String, String>,
 }
 
 pub fn set_task_info(snark_params: &SnarkTaskRequestParams) -> TaskInfo {
@@ -33,18 +51,47 @@ pub fn set_task_info(snark_params: &SnarkTaskRequestParams) -> TaskInfo {
         pub_in: snark_params.pub_in.clone(),
         post_config: snark_params.post_config.clone(),
         replicas_len: snark_params.replicas_len as usize,
+        proof_type: snark_params.proof_type.clone(),
         result: vec![],
         task_status: TaskStatus::Ready,
+        partitions: 0,
+        partitions_done: 0,
+        timings: PhaseTimings::default(),
+        client: String::default(),
+        submitted_at: Some(SystemTime::now()),
+        started_at: None,
+        trace_context: HashMap::new(),
     };
     task_info
 }
 
-fn get_post_config(post_config_u8: &Vec<u8>) -> Result<PoStConfig> {
+pub fn get_post_config(post_config_u8: &Vec<u8>) -> Result<PoStConfig> {
     let post_config_v = serde_json::from_slice(post_config_u8)?;
     let post_config = serde_json::from_value::<PoStConfig>(post_config_v)?;
     Ok(post_config)
 }
 
+/// an empty proof type is window post, as sent by clients older than the field
+pub fn get_proof_type(proof_type: &str) -> Result<ProofType> {
+    if proof_type.is_empty() {
+        return Ok(ProofType::default());
+    }
+    match ProofType::from_str(proof_type) {
+        Ok(t) => Ok(t),
+        Err(_) => Err(anyhow::Error::from(Error::InvalidParameters(format!(
+            "unknown proof type: {}",
+            proof_type
+        )))),
+    }
+}
+
+pub fn proof_type_of(post_config: &PoStConfig) -> ProofType {
+    match post_config.typ {
+        PoStType::Window => ProofType::WindowPoSt,
+        PoStType::Winning => ProofType::WinningPoSt,
+    }
+}
+
 pub async fn run_task(
     exit_rx: oneshot::Receiver<String>,
     mut do_task_signal_rx: UnboundedReceiver<String>,
@@ -56,55 +103,43 @@ pub async fn run_task(
             match do_task_signal_rx.recv().await {
                 Some(value) => {
                     if value == "ok".to_string() {
-                        let si1 = match srv_info.lock() {
-                            Ok(s) => s,
+                        let (t, generation, max_prove_time) = match srv_info.lock() {
+                            Ok(mut si) => {
+                                info!("start to do task: {}", si.task_info.task_id);
+                                si.task_info.task_status = TaskStatus::Working;
+                                si.task_info.started_at = Some(SystemTime::now());
+                                si.task_generation += 1;
+                                let max_prove_time =
+                                    si.max_prove_time_of(sector_size_of(&si.task_info));
+                                (si.task_info.clone(), si.task_generation, max_prove_time)
+                            }
                             Err(e) => {
                                 error!("get lock failed with error: {}", e);
                                 continue;
                             }
                         };
-
-                        info!("start to do task: {}", si1.task_info.task_id);
-                        let t = si1.task_info.clone();
-
-                        let post_config = get_post_config(&t.post_config);
-                        drop(si1);
-                        // run snark
-                        match post_config {
-                            Ok(p) => {
-                                let size = p.sector_size;
-                                let result = with_shape!(size.0, run_snark, t);
-
-                                let mut si2 = match srv_info.lock() {
-                                    Ok(s) => s,
-                                    Err(e) => {
-                                        error!("get lock failed with error: {}", e);
-                                        continue;
-                                    }
-                                };
-
-                                match result {
-                                    Ok(r) => {
-                                        info!("task {} done", si2.task_info.task_id);
-                                        si2.task_info.result = r;
-                                        si2.task_info.task_status = TaskStatus::Done;
-                                        si2.last_update_time = Instant::now();
-                                    }
-                                    Err(e) => {
-                                        error!(
-                                            "snark task {} failed with error: {}",
-                                            si2.task_info.task_id, e
-                                        );
-                                        si2.task_info.task_status = TaskStatus::Failed;
-                                        si2.error = e.to_string();
-                                        si2.last_update_time = Instant::now();
-                                    }
+                        // prove on the blocking pool, so rpc and timers keep running on the runtime
+                        let task_id = t.task_id.clone();
+                        let span = task_span(&t);
+                        let proving_span = span.clone();
+                        let si = srv_info.clone();
+                        let proving = task::spawn_blocking(move || {
+                            proving_span.in_scope(|| prove_task(t, generation, si))
+                        });
+                        let proved = match max_prove_time {
+                            Some(max) => match tokio::time::timeout(max, proving).await {
+                                Ok(proved) => proved,
+                                Err(_) => {
+                                    span.in_scope(|| {
+                                        time_out_task(&srv_info, generation, &task_id, max)
+                                    });
+                                    Ok(())
                                 }
-                                drop(si2)
-                            }
-                            Err(e) => {
-                                error!("parse post config with error:{}", e);
-                            }
+                            },
+                            None => proving.await,
+                        };
+                        if let Err(e) = proved {
+                            error!("prove task failed with error: {}", e);
                         }
                     } else {
                         error!("wrong signal {:?}", value);
@@ -132,88 +167,233 @@ pub async fn run_task(
     if is_exit_signal {
         let exit_start_time = Instant::now();
         let (mut is_working_logged, mut is_done_logged) = (false, false);
+        // saved once the server is unlocked
+        let mut abandoned = None;
         loop {
-            let mut si = match srv_info.lock() {
-                Ok(s) => s,
-                Err(e) => {
-                    error!("{}", e);
-                    continue;
-                }
-            };
-            match si.task_info.task_status {
-                TaskStatus::None => {
-                    info!("no task running, will exit immediately");
-                    si.status = ServerStatus::Unknown;
-                    si.last_update_time = Instant::now();
-                    break;
-                }
-                TaskStatus::Ready => {
-                    info!("task is ready but not start running, will exit immediately");
+            let exit_now = {
+                let mut si = match srv_info.lock() {
+                    Ok(s) => s,
+                    Err(e) => {
+                        error!("{}", e);
+                        continue;
+                    }
+                };
+                // a shutdown asked over rpc may be sooner than waiting for the result
+                let policy = si.shutdown.unwrap_or_default();
+                if policy == ShutdownPolicy::Immediate {
+                    if si.task_info.task_status == TaskStatus::Working {
+                        warn!(
+                            "task {} is abandoned by an immediate shutdown",
+                            si.task_info.task_id
+                        );
+                        si.task_info.task_status = TaskStatus::Failed;
+                        si.error = "abandoned by an immediate shutdown".to_string();
+                        abandoned = Some(history::record_task(&si));
+                    }
                     si.status = ServerStatus::Unknown;
                     si.last_update_time = Instant::now();
                     break;
                 }
-                TaskStatus::Working => {
-                    if !is_working_logged {
-                        is_working_logged = true;
-                        info!("task is running,will exit after task done and result returned");
+                match si.task_info.task_status {
+                    TaskStatus::None => {
+                        info!("no task running, will exit immediately");
+                        si.status = ServerStatus::Unknown;
+                        si.last_update_time = Instant::now();
+                        true
                     }
-                    continue;
-                }
-                TaskStatus::Done => {
-                    if Instant::now().duration_since(exit_start_time)
-                        > si.server_exit_time_out_after_task_done
-                    {
-                        warn!("worker has wait 5minute,force exited");
+                    TaskStatus::Ready => {
+                        info!("task is ready but not start running, will exit immediately");
                         si.status = ServerStatus::Unknown;
                         si.last_update_time = Instant::now();
-                        break;
-                    } else {
-                        if !is_done_logged {
-                            is_done_logged = true;
-                            info!("task is done,waiting for miner to get result back");
+                        true
+                    }
+                    TaskStatus::Working => {
+                        if !is_working_logged {
+                            is_working_logged = true;
+                            info!("task is running,will exit after task done and result returned");
                         }
-                        continue;
+                        false
+                    }
+                    TaskStatus::Done if policy == ShutdownPolicy::AfterTask => {
+                        info!(
+                            "task is done, will exit without waiting for miner to get result back"
+                        );
+                        si.status = ServerStatus::Unknown;
+                        si.last_update_time = Instant::now();
+                        true
+                    }
+                    TaskStatus::Done => {
+                        if Instant::now().duration_since(exit_start_time)
+                            > si.server_exit_time_out_after_task_done
+                        {
+                            warn!("worker has wait 5minute,force exited");
+                            si.status = ServerStatus::Unknown;
+                            si.last_update_time = Instant::now();
+                            true
+                        } else {
+                            if !is_done_logged {
+                                is_done_logged = true;
+                                info!("task is done,waiting for miner to get result back");
+                            }
+                            false
+                        }
+                    }
+                    TaskStatus::Returned => {
+                        info!("task result was returned,will exit immediately");
+                        si.status = ServerStatus::Unknown;
+                        si.last_update_time = Instant::now();
+                        true
+                    }
+                    TaskStatus::Failed => {
+                        si.status = ServerStatus::Unknown;
+                        si.last_update_time = Instant::now();
+                        true
                     }
-                }
-                TaskStatus::Returned => {
-                    info!("task result was returned,will exit immediately");
-                    si.status = ServerStatus::Unknown;
-                    si.last_update_time = Instant::now();
-                    break;
-                }
-                TaskStatus::Failed => {
-                    si.status = ServerStatus::Unknown;
-                    si.last_update_time = Instant::now();
-                    break;
                 }
             };
+            if exit_now {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        if let Some(record) = abandoned {
+            record.save();
+            if let Err(e) = child::kill_prove_worker(&srv_info) {
+                error!("kill prove worker failed with error: {}", e);
+            }
         }
     }
     info!("task worker exited");
 }
 
-fn run_snark<Tree: 'static + MerkleTreeTrait>(task_info: TaskInfo) -> Result<Vec<u8>> {
-    let post_config_v = serde_json::from_slice(&task_info.post_config)?;
-    let post_config = serde_json::from_value::<PoStConfig>(post_config_v)?;
+/// run snark with the handler of the task's proof type
+pub fn prove_with_handler(t: TaskInfo, ctx: &TaskContext) -> Result<Vec<u8>> {
+    match get_proof_type(&t.proof_type) {
+        // a panic fails this task only, the worker goes on with later ones
+        Ok(proof_type) => match panic::catch_unwind(AssertUnwindSafe(|| {
+            handler::handler_of(proof_type).prove(t, ctx)
+        })) {
+            Ok(r) => r,
+            Err(p) => Err(anyhow::Error::from(Error::from(p))),
+        },
+        Err(e) => Err(e),
+    }
+}
+
+/// every log line of the task carries its fields, also those of partition workers
+pub fn task_span(t: &TaskInfo) -> Span {
+    let span = info_span!(
+        "task",
+        task_id = %t.task_id,
+        miner_id = %t.client,
+        sector_size = sector_size_of(t).unwrap_or_default(),
+        proof_type = %t.proof_type
+    );
+    // proving is part of the trace of the client that submitted the task
+    trace::set_parent(&span, trace::from_headers(&t.trace_context));
+    span
+}
+
+pub fn sector_size_of(t: &TaskInfo) -> Option<u64> {
+    get_proof_type(&t.proof_type)
+        .and_then(|proof_type| handler::handler_of(proof_type).sector_size(t))
+        .ok()
+}
+
+// fail a task proving too long, a child process is killed, while an in-process proof can not
+// be stopped, the server then takes no task until it ends and its result is dropped
+fn time_out_task(
+    srv_info: &Arc<Mutex<ServerInfo>>,
+    generation: u64,
+    task_id: &str,
+    max_prove_time: Duration,
+) {
+    let record = match srv_info.lock() {
+        Ok(mut si) => {
+            if si.task_generation != generation || si.task_info.task_status != TaskStatus::Working {
+                return;
+            }
+            si.task_info.task_status = TaskStatus::Failed;
+            si.error = Error::TaskTimeOut(task_id.to_string()).to_string();
+            si.tasks_timed_out += 1;
+            si.orphaned_proof = true;
+            si.last_update_time = Instant::now();
+            warn!(
+                "task {} is still proving after {:?}, failed it, {} tasks timed out so far",
+                task_id, max_prove_time, si.tasks_timed_out
+            );
+            history::record_task(&si)
+        }
+        Err(e) => {
+            error!("get lock failed with error: {}", e);
+            return;
+        }
+    };
+    record.save();
+    match child::kill_prove_worker(srv_info) {
+        Ok(true) => info!("prove worker of task {} killed", task_id),
+        Ok(false) => {}
+        Err(e) => error!("kill prove worker failed with error: {}", e),
+    }
+}
+
+// run by the blocking pool, the result is written back here so it is kept even if the worker exits
+fn prove_task(t: TaskInfo, generation: u64, srv_info: Arc<Mutex<ServerInfo>>) {
+    let task_id = t.task_id.clone();
+    let prove_worker_exe = match srv_info.lock() {
+        Ok(si) => si.prove_worker_exe.clone(),
+        Err(e) => {
+            error!("get lock failed with error: {}", e);
+            return;
+        }
+    };
+    let start = Instant::now();
+    let result = match prove_worker_exe {
+        Some(exe) => child::prove_in_child(&exe, t, generation, &srv_info),
+        None => prove_with_handler(t, &TaskContext::new(srv_info.clone(), generation)),
+    };
+    let total = start.elapsed();
 
-    let vanilla_params = window_post_setup_params(&post_config);
-    let partitions = get_partitions_for_window_post(task_info.replicas_len as usize, &post_config);
-    let setup_params = compound_proof::SetupParams {
-        vanilla_params,
-        partitions,
-        priority: post_config.priority,
+    let mut si = match srv_info.lock() {
+        Ok(s) => s,
+        Err(e) => {
+            error!("get lock failed with error: {}", e);
+            return;
+        }
     };
-    let pub_params: compound_proof::PublicParams<'_, FallbackPoSt<'_, Tree>> =
-        FallbackPoStCompound::setup(&setup_params)?;
-    let vanilla_v = serde_json::from_slice(&task_info.vanilla_proof)?;
-    let pub_in_v = serde_json::from_slice(&task_info.pub_in)?;
-    let groth_params = get_post_params::<Tree>(&post_config)?;
-    let proof = FallbackPoStCompound::prove_with_vanilla_by_snark_server(
-        &pub_params,
-        pub_in_v,
-        vanilla_v,
-        &groth_params,
-    )?;
-    proof.to_vec()
+    // the task was timed out meanwhile
+    if si.task_generation != generation || si.task_info.task_status != TaskStatus::Working {
+        warn!("task {} ended after it was failed, result dropped", task_id);
+        if si.orphaned_proof {
+            si.orphaned_proof = false;
+            if si.status == ServerStatus::Unknown && si.shutdown.is_none() {
+                si.status = ServerStatus::Free;
+                si.last_update_time = Instant::now();
+            }
+            info!("timed out proof ended, server takes tasks again");
+        }
+        return;
+    }
+    match result {
+        Ok(r) => {
+            si.task_info.timings.total = total;
+            info!("task {} done, {}", task_id, si.task_info.timings);
+            si.task_info.result = r;
+            si.task_info.task_status = TaskStatus::Done;
+            si.last_update_time = Instant::now();
+        }
+        Err(e) => {
+            error!(
+                "snark task {} failed with error: {}",
+                si.task_info.task_id, e
+            );
+            si.task_info.timings.total = total;
+            si.task_info.task_status = TaskStatus::Failed;
+            si.error = e.to_string();
+            si.last_update_time = Instant::now();
+        }
+    }
+    let record = history::record_task(&si);
+    drop(si);
+    record.save();
 }
@@ -0,0 +1,56 @@
+use crate::snark_proof_grpc;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Time a task spent in each phase of proving, summed over its partitions,
+/// while `total` is the wall clock time of the whole task
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct PhaseTimings {
+    // json decoding of the vanilla proof and public inputs
+    pub decode: Duration,
+    // public params of the compound proof
+    pub setup: Duration,
+    // groth params, from the parameter cache or from disk
+    pub params_load: Duration,
+    pub prove: Duration,
+    pub total: Duration,
+}
+
+impl PhaseTimings {
+    pub fn add(&mut self, other: &PhaseTimings) {
+        self.decode += other.decode;
+        self.setup += other.setup;
+        self.params_load += other.params_load;
+        self.prove += other.prove;
+        self.total += other.total;
+    }
+
+    pub fn to_proto(&self) -> snark_proof_grpc::PhaseTimings {
+        snark_proof_grpc::PhaseTimings {
+            decode_ms: self.decode.as_millis() as u64,
+            setup_ms: self.setup.as_millis() as u64,
+            params_load_ms: self.params_load.as_millis() as u64,
+            prove_ms: self.prove.as_millis() as u64,
+            total_ms: self.total.as_millis() as u64,
+        }
+    }
+}
+
+impl fmt::Display for PhaseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "decode {:?}, setup {:?}, params load {:?}, prove {:?}, total {:?}",
+            self.decode, self.setup, self.params_load, self.prove, self.total
+        )
+    }
+}
+
+/// run `f` and add the time it took to `phase`
+pub fn timed<R>(phase: &mut Duration, f: impl FnOnce() -> R) -> R {
+    let start = Instant::now();
+    let r = f();
+    *phase += start.elapsed();
+    r
+}
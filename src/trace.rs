@@ -0,0 +1,102 @@
+use anyhow::Result;
+use opentelemetry::propagation::{Extractor, Injector, TextMapPropagator};
+use opentelemetry::trace::TraceContextExt;
+use opentelemetry::{Context, KeyValue};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::{trace, Resource};
+use std::collections::HashMap;
+use tonic::metadata::{MetadataKey, MetadataMap, MetadataValue};
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+struct MetadataExtractor<'a>(&'a MetadataMap);
+
+impl Extractor for MetadataExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|v| v.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0
+            .keys()
+            .filter_map(|k| match k {
+                tonic::metadata::KeyRef::Ascii(k) => Some(k.as_str()),
+                tonic::metadata::KeyRef::Binary(_) => None,
+            })
+            .collect()
+    }
+}
+
+struct MetadataInjector<'a>(&'a mut MetadataMap);
+
+impl Injector for MetadataInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(key), Ok(value)) = (
+            MetadataKey::from_bytes(key.as_bytes()),
+            MetadataValue::from_str(&value),
+        ) {
+            self.0.insert(key, value);
+        }
+    }
+}
+
+/// the trace context a client sent in the request metadata, empty without one
+pub fn extract(metadata: &MetadataMap) -> Context {
+    TraceContextPropagator::new().extract(&MetadataExtractor(metadata))
+}
+
+/// add the trace context to request metadata
+pub fn inject(cx: &Context, metadata: &mut MetadataMap) {
+    TraceContextPropagator::new().inject_context(cx, &mut MetadataInjector(metadata))
+}
+
+/// the trace context as headers, to keep it along a task
+pub fn to_headers(cx: &Context) -> HashMap<String, String> {
+    let mut headers = HashMap::new();
+    TraceContextPropagator::new().inject_context(cx, &mut headers);
+    headers
+}
+
+pub fn from_headers(headers: &HashMap<String, String>) -> Context {
+    TraceContextPropagator::new().extract(headers)
+}
+
+/// the context of the current span when spans are exported,
+/// else the context the caller attached with opentelemetry itself
+pub fn current_context() -> Context {
+    let cx = Span::current().context();
+    if cx.span().span_context().is_valid() {
+        cx
+    } else {
+        Context::current()
+    }
+}
+
+/// make `span` a child of the remote span in `cx`, if there is one
+pub fn set_parent(span: &Span, cx: Context) {
+    if cx.span().span_context().is_valid() {
+        span.set_parent(cx);
+    }
+}
+
+/// a tracer exporting spans over OTLP/HTTP to `endpoint`, like `http://localhost:4318`
+pub fn otlp_tracer(endpoint: &str, service_name: &str) -> Result<trace::Tracer> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')));
+    let config = trace::config().with_resource(Resource::new(vec![KeyValue::new(
+        "service.name",
+        service_name.to_string(),
+    )]));
+    Ok(opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(config)
+        .install_simple()?)
+}
+
+/// export the spans not exported yet, before the process exits
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
@@ -1,13 +1,13 @@
+use crate::listen::ListenAddr;
+use anyhow::Result;
 use clap::crate_version;
-use log::{error, info};
 use std::env;
-use std::fs::File;
-use std::io::{BufReader, Read};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
-use std::{
-    fs::{remove_file, write},
-    process,
-};
+use std::process;
+use tracing::info;
 
 pub fn set_commit_env() {
     if let Ok(x) = process::Command::new("git")
@@ -34,74 +34,73 @@ pub fn version() -> &'static str {
     }
 }
 
-pub fn lock_file_path() -> PathBuf {
-    dirs::home_dir().unwrap().join(".fil_wdpost_server.lock")
+/// Held while a server runs, so a second one of the same instance refuses to start.
+/// The OS drops the flock when the process exits, even on a crash, the file itself is left.
+#[derive(Debug)]
+pub struct InstanceLock {
+    pub path: PathBuf,
+    // the lock lives as long as the file stays open
+    _file: File,
 }
 
-pub fn is_file_lock_exist() -> bool {
-    let lock_path = lock_file_path();
-    info!("lock_path: {:?}", lock_path.clone().as_path());
-    Path::new(lock_path.as_path()).exists()
-}
-
-pub fn write_pid_into_file_lock(pid: &Vec<u8>) -> Result<(), anyhow::Error> {
-    let lock_path = lock_file_path();
-    let result = write(lock_path, pid);
-    match result {
-        Ok(_) => Ok(()),
-        Err(e) => Err(anyhow::Error::msg(e.to_string())),
+impl InstanceLock {
+    /// lock the instance in the home dir
+    pub fn acquire(instance: &str) -> Result<Self> {
+        match dirs::home_dir() {
+            Some(home) => InstanceLock::acquire_in(&home, instance),
+            None => Err(anyhow::Error::msg("no home dir for the lock file")),
+        }
     }
-}
 
-pub fn check_process_is_running_by_pid() -> Option<u32> {
-    let lock_path = lock_file_path().to_str().unwrap().to_string();
-    let pid = read_pid(lock_path);
-    if pid == 0 {
-        None
-    } else {
-        let pid_str = pid.to_string();
-        let args = vec!["-p", &pid_str, "-o", "pid="];
-        let ps_cmd_out = process::Command::new("ps")
-            .args(args)
-            .output()
-            .expect("failed to execute ps -p");
-        if ps_cmd_out.status.success() {
-            if String::from_utf8(ps_cmd_out.stdout)
-                .unwrap()
-                .contains(&pid_str.to_string())
-            {
-                Some(pid)
-            } else {
-                None
+    pub fn acquire_in(dir: &Path, instance: &str) -> Result<Self> {
+        let path = lock_file_path(dir, instance);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let e = io::Error::last_os_error();
+            if e.kind() != io::ErrorKind::WouldBlock {
+                return Err(e.into());
             }
-        } else {
-            None
+            let mut pid = String::new();
+            file.read_to_string(&mut pid)?;
+            return Err(anyhow::Error::msg(format!(
+                "instance {} is already running with pid {}, locked {:?}",
+                instance,
+                pid.trim(),
+                path
+            )));
         }
+        // the pid is only for people, the flock decides who runs
+        file.set_len(0)?;
+        file.write_all(process::id().to_string().as_bytes())?;
+        file.flush()?;
+        info!("instance {} locked {:?}", instance, path);
+        Ok(InstanceLock { path, _file: file })
     }
 }
 
-pub fn del_file_lock() {
-    let lock_path = lock_file_path();
-    match remove_file(lock_path) {
-        Ok(_) => {}
-        Err(e) => {
-            error!("{}", e);
-        }
-    };
+/// name an instance after its first listen address, like `0.0.0.0_50051`
+pub fn instance_name(listen_addrs: &[ListenAddr]) -> String {
+    match listen_addrs.first() {
+        Some(addr) => addr
+            .to_string()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect(),
+        None => "default".to_string(),
+    }
 }
 
-pub fn read_pid(path: String) -> u32 {
-    match File::open(path) {
-        Ok(data) => {
-            let mut buf_reader = BufReader::new(data);
-            let mut contents = String::new();
-            buf_reader
-                .read_to_string(&mut contents)
-                .expect("read pid failed");
-            contents
-                .parse::<u32>()
-                .expect("parse pid error from pid file")
-        }
-        Err(_) => 0,
-    }
+pub fn lock_file_path(dir: &Path, instance: &str) -> PathBuf {
+    dir.join(format!(".fil_wdpost_server.{}.lock", instance))
 }
@@ -0,0 +1,66 @@
+mod common;
+
+use common::task_errors;
+use filecoin_proofs::{SectorSize, SECTOR_SIZE_2_KIB};
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use window_post_snark_server::child;
+use window_post_snark_server::error::Error;
+use window_post_snark_server::params;
+use window_post_snark_server::server::ServerInfo;
+use window_post_snark_server::snark_proof_grpc::SnarkTaskRequestParams;
+use window_post_snark_server::status::ProofType;
+use window_post_snark_server::tasks::TaskInfo;
+
+#[test]
+fn test_prove_in_child() {
+    // the child panics on a sector size with_shape! has no tree shape for
+    let mut post_config = params::window_post_config(SECTOR_SIZE_2_KIB);
+    post_config.sector_size = SectorSize(3 << 10);
+    let msgs = task_errors(
+        Some(PathBuf::from(env!(
+            "CARGO_BIN_EXE_window-post-snark-server"
+        ))),
+        50230,
+        vec![SnarkTaskRequestParams {
+            task_id: "child-task".to_string(),
+            vanilla_proof: vec![],
+            pub_in: vec![],
+            post_config: serde_json::to_vec(&post_config).unwrap(),
+            replicas_len: 1,
+            proof_type: ProofType::WindowPoSt.to_string(),
+        }],
+    );
+    assert!(
+        msgs[0].contains("panicked: unsupported sector size: 3072"),
+        "{}",
+        msgs[0]
+    );
+}
+
+#[test]
+fn test_kill_prove_worker() {
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("hanging-worker");
+    std::fs::write(&exe, "#!/bin/sh\nexec sleep 60\n").unwrap();
+    std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let srv_info = Arc::new(Mutex::new(ServerInfo::default()));
+    let si = srv_info.clone();
+    let proving = thread::spawn(move || child::prove_in_child(&exe, TaskInfo::default(), 0, &si));
+    while srv_info.lock().unwrap().prove_worker_pid.is_none() {
+        thread::sleep(Duration::from_millis(10));
+    }
+    assert!(child::kill_prove_worker(&srv_info).unwrap());
+
+    let e = proving.join().unwrap().unwrap_err();
+    match e.downcast::<Error>().unwrap() {
+        Error::ProveWorkerFailed(msg) => assert!(msg.contains("signal: 9"), "{}", msg),
+        e => panic!("unexpected error: {}", e),
+    }
+    assert!(srv_info.lock().unwrap().prove_worker_pid.is_none());
+    assert!(!child::kill_prove_worker(&srv_info).unwrap());
+}
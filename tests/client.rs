@@ -535,6 +535,7 @@ fn generate_window_post<Tree: 'static + MerkleTreeTrait>(
             pub_in: serde_json::to_vec(&pub_inputs)?,
             post_config: serde_json::to_vec(&post_config)?,
             replicas_len: replicas.len() as u32,
+            proof_type: String::default(),
         });
 
         match rt.block_on(async { client.do_snark_task(req_do_task).await }) {
@@ -0,0 +1,57 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub type Exports = Arc<Mutex<Vec<Vec<u8>>>>;
+
+// stands in for an OTLP/HTTP collector, keeping the body of every export
+pub fn spawn_collector() -> (String, Exports) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let endpoint = format!("http://{}", listener.local_addr().unwrap());
+    let exports = Exports::default();
+    let kept = exports.clone();
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let kept = kept.clone();
+            thread::spawn(move || serve_exports(stream.unwrap(), kept));
+        }
+    });
+    (endpoint, exports)
+}
+
+fn serve_exports(mut stream: TcpStream, exports: Exports) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    loop {
+        let mut content_length = 0;
+        let mut path = String::new();
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                return;
+            }
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if path.is_empty() {
+                path = line.to_string();
+            } else if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+        assert!(path.contains("/v1/traces"), "{}", path);
+        exports.lock().unwrap().push(body);
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n")
+            .unwrap();
+    }
+}
+
+pub fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+    haystack.windows(needle.len()).any(|w| w == needle)
+}
@@ -0,0 +1,151 @@
+#![allow(dead_code)]
+
+pub mod collector;
+pub mod post;
+
+use serde_json::json;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::{mpsc, oneshot};
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::error::Error;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server;
+use window_post_snark_server::server::{ServerInfo, WindowPostSnarkServer};
+use window_post_snark_server::snark_proof_grpc::SnarkTaskRequestParams;
+use window_post_snark_server::status::{ProofType, ServerStatus, TaskStatus};
+use window_post_snark_server::{params, tasks};
+
+pub const FAKE_FAILURE: &str = "fake failure";
+
+#[derive(Clone)]
+pub enum FakeResult {
+    Proof(Vec<u8>),
+    // the task's vanilla proof is returned as its proof
+    Echo,
+    Fail,
+    // the server drops the task and is free again, as if it restarted
+    Lost,
+}
+
+// stands in for tasks::run_task, finishing every task at once with the given result
+pub async fn fake_worker(
+    mut rx: UnboundedReceiver<String>,
+    srv_info: Arc<Mutex<ServerInfo>>,
+    result: FakeResult,
+) {
+    while rx.recv().await.is_some() {
+        let mut si = srv_info.lock().unwrap();
+        match &result {
+            FakeResult::Proof(r) => {
+                si.task_info.result = r.clone();
+                si.task_info.task_status = TaskStatus::Done;
+            }
+            FakeResult::Echo => {
+                si.task_info.result = si.task_info.vanilla_proof.clone();
+                si.task_info.task_status = TaskStatus::Done;
+            }
+            FakeResult::Fail => {
+                si.error = FAKE_FAILURE.to_string();
+                si.task_info.task_status = TaskStatus::Failed;
+            }
+            FakeResult::Lost => {
+                si.status = ServerStatus::Free;
+            }
+        }
+        si.last_update_time = Instant::now();
+    }
+}
+
+/// serve a server on 127.0.0.1:`port` whose tasks end with `result`
+pub fn spawn_fake_server(
+    rt: &Runtime,
+    port: u16,
+    result: FakeResult,
+) -> (oneshot::Sender<String>, Arc<Mutex<ServerInfo>>) {
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (exit_tx, exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    let srv_info = sv.server_info.clone();
+    rt.spawn(fake_worker(run_task_rx, sv.server_info.clone(), result));
+    rt.spawn(server::run_server(
+        exit_rx,
+        sv,
+        vec![format!("127.0.0.1:{}", port).parse::<ListenAddr>().unwrap()],
+    ));
+    (exit_tx, srv_info)
+}
+
+/// a 2KiB window post task over `sectors` sectors, with one fake vanilla proof per partition
+pub fn fake_window_post_task(task_id: &str, sectors: u64) -> SnarkTaskRequestParams {
+    let post_config = params::window_post_config(2048);
+    let partitions = (sectors as usize + post_config.sector_count - 1) / post_config.sector_count;
+    let vanilla_proofs: Vec<_> = (0..partitions)
+        .map(|k| json!({ "sectors": [format!("vanilla-{}", k)] }))
+        .collect();
+    let pub_in = json!({
+        "randomness": vec![0u8; 32],
+        "prover_id": vec![1u8; 32],
+        "sectors": (0..sectors).map(|id| json!({ "id": id })).collect::<Vec<_>>(),
+        "k": null,
+    });
+    SnarkTaskRequestParams {
+        task_id: task_id.to_string(),
+        vanilla_proof: serde_json::to_vec(&vanilla_proofs).unwrap(),
+        pub_in: serde_json::to_vec(&pub_in).unwrap(),
+        post_config: serde_json::to_vec(&post_config).unwrap(),
+        replicas_len: sectors as u32,
+        proof_type: ProofType::WindowPoSt.to_string(),
+    }
+}
+
+/// run the tasks one after another on a real server, each of them is expected to fail
+pub fn task_errors(
+    prove_worker_exe: Option<PathBuf>,
+    port: u16,
+    tasks: Vec<SnarkTaskRequestParams>,
+) -> Vec<String> {
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    sv.server_info.lock().unwrap().prove_worker_exe = prove_worker_exe;
+    rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec![format!("127.0.0.1:{}", port).parse::<ListenAddr>().unwrap()],
+    ));
+
+    let errors = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let addr = format!("http://127.0.0.1:{}", port);
+        let mut c = SnarkClient::connect(&addr, Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.set_lock_wait_time_out(Duration::from_secs(10));
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        let mut errors = vec![];
+        for params in tasks {
+            let e = c.prove_task(params).await.unwrap_err();
+            match e.downcast::<Error>().unwrap() {
+                Error::TaskFailedWithError(msg) => errors.push(msg),
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+        errors
+    });
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+    errors
+}
@@ -0,0 +1,134 @@
+use filecoin_hashers::{Domain, HashFunction, Hasher};
+use filecoin_proofs::caches::get_post_verifying_key;
+use filecoin_proofs::parameters::{window_post_setup_params, winning_post_setup_params};
+use filecoin_proofs::{get_partitions_for_window_post, PoStConfig, PoStType, SectorShape2KiB};
+use rand::SeedableRng;
+use rand_xorshift::XorShiftRng;
+use storage_proofs_core::compound_proof::{self, CompoundProof};
+use storage_proofs_core::merkle::{generate_tree, MerkleTreeTrait};
+use storage_proofs_core::multi_proof::MultiProof;
+use storage_proofs_core::proof::ProofScheme;
+use storage_proofs_core::util::NODE_SIZE;
+use storage_proofs_core::TEST_SEED;
+use storage_proofs_post::fallback::{
+    ChallengeRequirements, FallbackPoSt, FallbackPoStCompound, PrivateInputs, PrivateSector,
+    PublicInputs, PublicSector,
+};
+use tempfile::TempDir;
+
+pub type Tree = SectorShape2KiB;
+
+type TreeDomain = <<Tree as MerkleTreeTrait>::Hasher as Hasher>::Domain;
+
+/// a real 2KiB post task over fake sectors, with what it takes to verify its proof
+pub struct PoStTask {
+    pub post_config: PoStConfig,
+    pub partitions: usize,
+    pub vanilla_proof: Vec<u8>,
+    pub pub_in: Vec<u8>,
+    pub_params: compound_proof::PublicParams<'static, FallbackPoSt<'static, Tree>>,
+    pub_inputs: PublicInputs<TreeDomain>,
+    // holds the generated groth params
+    _cache_dir: TempDir,
+}
+
+impl PoStTask {
+    /// generate groth params of `post_config` into a new parameter cache dir and the vanilla
+    /// proofs of `sectors` sectors. The cache dir is read once by storage-proofs settings, so
+    /// this must come before any params access of the test process, and only once.
+    pub fn new(post_config: PoStConfig, sectors: usize) -> Self {
+        let cache_dir = tempfile::tempdir().unwrap();
+        std::env::set_var("FIL_PROOFS_PARAMETER_CACHE", cache_dir.path());
+
+        let rng = &mut XorShiftRng::from_seed(TEST_SEED);
+        let (vanilla_params, partitions) = match post_config.typ {
+            PoStType::Window => (
+                window_post_setup_params(&post_config),
+                get_partitions_for_window_post(sectors, &post_config),
+            ),
+            PoStType::Winning => (winning_post_setup_params(&post_config).unwrap(), None),
+        };
+        let pub_params = FallbackPoStCompound::<Tree>::setup(&compound_proof::SetupParams {
+            vanilla_params,
+            partitions,
+            priority: false,
+        })
+        .unwrap();
+        // generated params land in the cache dir, where the prover loads them from
+        FallbackPoStCompound::<Tree>::groth_params(Some(rng), &pub_params.vanilla_params).unwrap();
+
+        // fake sectors, only their tree_r_last is needed to prove
+        let tree_dir = tempfile::tempdir().unwrap();
+        let leaves = post_config.sector_size.0 as usize / NODE_SIZE;
+        let mut trees = vec![];
+        let mut pub_sectors = vec![];
+        let mut comms = vec![];
+        for id in 0..sectors {
+            let (_, tree) = generate_tree::<Tree, _>(rng, leaves, Some(tree_dir.path().into()));
+            let comm_c = TreeDomain::random(rng);
+            let comm_r_last = tree.root();
+            pub_sectors.push(PublicSector {
+                id: (id as u64 + 1).into(),
+                comm_r: <<Tree as MerkleTreeTrait>::Hasher as Hasher>::Function::hash2(
+                    &comm_c,
+                    &comm_r_last,
+                ),
+            });
+            comms.push((comm_c, comm_r_last));
+            trees.push(tree);
+        }
+        let pub_inputs = PublicInputs {
+            randomness: TreeDomain::random(rng),
+            prover_id: TreeDomain::random(rng),
+            sectors: pub_sectors,
+            k: None,
+        };
+        let priv_sectors: Vec<_> = trees
+            .iter()
+            .zip(comms)
+            .map(|(tree, (comm_c, comm_r_last))| PrivateSector {
+                tree,
+                comm_c,
+                comm_r_last,
+            })
+            .collect();
+        let partitions = partitions.unwrap_or(1);
+        let vanilla_proofs = FallbackPoSt::<Tree>::prove_all_partitions(
+            &pub_params.vanilla_params,
+            &pub_inputs,
+            &PrivateInputs {
+                sectors: &priv_sectors,
+            },
+            partitions,
+        )
+        .unwrap();
+
+        PoStTask {
+            partitions,
+            vanilla_proof: serde_json::to_vec(&vanilla_proofs).unwrap(),
+            pub_in: serde_json::to_vec(&pub_inputs).unwrap(),
+            post_config,
+            pub_params,
+            pub_inputs,
+            _cache_dir: cache_dir,
+        }
+    }
+
+    pub fn post_config_json(&self) -> Vec<u8> {
+        serde_json::to_vec(&self.post_config).unwrap()
+    }
+
+    pub fn verify(&self, proof: &[u8]) -> bool {
+        let vk = get_post_verifying_key::<Tree>(&self.post_config).unwrap();
+        let multi_proof = MultiProof::new_from_reader(Some(self.partitions), proof, &vk).unwrap();
+        FallbackPoStCompound::verify(
+            &self.pub_params,
+            &self.pub_inputs,
+            &multi_proof,
+            &ChallengeRequirements {
+                minimum_challenge_count: self.post_config.challenge_count,
+            },
+        )
+        .unwrap()
+    }
+}
@@ -0,0 +1,56 @@
+use window_post_snark_server::cpu;
+use window_post_snark_server::cpu::CpuSet;
+use window_post_snark_server::server::ServerInfo;
+
+#[test]
+fn test_parse_cores() {
+    assert_eq!(cpu::parse_cores("0-3,8").unwrap(), vec![0, 1, 2, 3, 8]);
+    assert_eq!(cpu::parse_cores(" 5, 2-3,3 ").unwrap(), vec![2, 3, 5]);
+    assert!(cpu::parse_cores("3-1").is_err());
+    assert!(cpu::parse_cores("a-b").is_err());
+    assert!(cpu::parse_cores("").is_err());
+    // a core past the cpu set would panic when pinned
+    assert!(cpu::parse_cores(&cpu::CORES_MAX.to_string()).is_err());
+    assert!(cpu::parse_cores("0-4000000000").is_err());
+}
+
+#[test]
+fn test_install_pins_pool_threads() {
+    let cpu_set = CpuSet {
+        threads: Some(2),
+        cores: vec![0],
+    };
+    let (threads, cores) = cpu_set
+        .install(|| {
+            (
+                rayon::current_num_threads(),
+                cpu::current_thread_cores().unwrap(),
+            )
+        })
+        .unwrap();
+    assert_eq!(threads, 2);
+    assert_eq!(cores, vec![0]);
+}
+
+#[test]
+fn test_cpu_set_of_slot() {
+    let global = CpuSet {
+        threads: Some(4),
+        cores: vec![],
+    };
+    let mut si = ServerInfo {
+        cpu_set: global.clone(),
+        ..ServerInfo::default()
+    };
+    assert_eq!(si.cpu_set_of_slot(3), global);
+
+    let slot = |cores: Vec<usize>| CpuSet {
+        threads: None,
+        cores,
+    };
+    si.slot_cpu_sets = vec![slot(vec![0, 1]), slot(vec![2, 3])];
+    assert_eq!(si.cpu_set_of_slot(0), slot(vec![0, 1]));
+    assert_eq!(si.cpu_set_of_slot(1), slot(vec![2, 3]));
+    assert_eq!(si.cpu_set_of_slot(2), slot(vec![0, 1]));
+    assert_eq!(si.slot_cpu_sets[1].num_threads(), Some(2));
+}
@@ -0,0 +1,73 @@
+mod common;
+
+use common::task_errors;
+use filecoin_proofs::{SectorSize, SECTOR_SIZE_2_KIB};
+use storage_proofs_core::api_version::ApiVersion;
+use window_post_snark_server::params;
+use window_post_snark_server::porep::SealCommitPhase2Inputs;
+use window_post_snark_server::snark_proof_grpc::SnarkTaskRequestParams;
+use window_post_snark_server::status::ProofType;
+
+#[test]
+fn test_seal_commit_phase2_handler() {
+    let inputs = SealCommitPhase2Inputs {
+        sector_size: 3 << 10,
+        porep_id: [0; 32],
+        api_version: ApiVersion::V1_1_0,
+        prover_id: [0; 32],
+        sector_id: 1,
+    };
+    let msg = &task_errors(
+        None,
+        50220,
+        vec![SnarkTaskRequestParams {
+            task_id: "c2-task".to_string(),
+            vanilla_proof: vec![],
+            pub_in: serde_json::to_vec(&inputs).unwrap(),
+            post_config: vec![],
+            replicas_len: 1,
+            proof_type: ProofType::SealCommitPhase2.to_string(),
+        }],
+    )[0];
+    assert!(msg.contains("unsupported sector size: 3072"), "{}", msg);
+}
+
+#[test]
+fn test_unknown_proof_type() {
+    let msg = &task_errors(
+        None,
+        50221,
+        vec![SnarkTaskRequestParams {
+            task_id: "unknown-task".to_string(),
+            vanilla_proof: vec![],
+            pub_in: vec![],
+            post_config: vec![],
+            replicas_len: 1,
+            proof_type: "ReplicaUpdate".to_string(),
+        }],
+    )[0];
+    assert!(msg.contains("unknown proof type: ReplicaUpdate"), "{}", msg);
+}
+
+#[test]
+fn test_panic_fails_task_only() {
+    // with_shape! panics on a sector size it has no tree shape for
+    let mut post_config = params::window_post_config(SECTOR_SIZE_2_KIB);
+    post_config.sector_size = SectorSize(3 << 10);
+    let task = |task_id: &str| SnarkTaskRequestParams {
+        task_id: task_id.to_string(),
+        vanilla_proof: vec![],
+        pub_in: vec![],
+        post_config: serde_json::to_vec(&post_config).unwrap(),
+        replicas_len: 1,
+        proof_type: ProofType::WindowPoSt.to_string(),
+    };
+    let msgs = task_errors(None, 50222, vec![task("panic-1"), task("panic-2")]);
+    for msg in msgs {
+        assert!(
+            msg.contains("panicked: unsupported sector size: 3072"),
+            "{}",
+            msg
+        );
+    }
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::fake_window_post_task;
+use std::fs;
+use std::time::{Duration, SystemTime};
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use tonic::Request;
+use window_post_snark_server::client::{new_client, SnarkClient};
+use window_post_snark_server::history::{TaskFilter, TaskHistory, TaskRecord, TASK_HISTORY_FILE};
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server::WindowPostSnarkServer;
+use window_post_snark_server::snark_proof_grpc::ListTasksRequest;
+use window_post_snark_server::status::TaskStatus;
+use window_post_snark_server::timings::PhaseTimings;
+use window_post_snark_server::{server, tasks};
+
+fn record(task_id: &str, status: TaskStatus, submitted_at: SystemTime) -> TaskRecord {
+    TaskRecord {
+        task_id: task_id.to_string(),
+        client: "miner".to_string(),
+        proof_type: "WindowPoSt".to_string(),
+        sector_size: 2048,
+        partitions: 1,
+        submitted_at,
+        started_at: submitted_at,
+        ended_at: submitted_at + Duration::from_secs(1),
+        status,
+        error: String::default(),
+        duration: Duration::from_secs(1),
+        timings: PhaseTimings::default(),
+    }
+}
+
+fn ids(records: Vec<TaskRecord>) -> Vec<String> {
+    records.into_iter().map(|r| r.task_id).collect()
+}
+
+#[test]
+fn test_task_history() {
+    let t0 = SystemTime::now();
+    let at = |secs| t0 + Duration::from_secs(secs);
+    let dir = tempfile::tempdir().unwrap();
+    let mut history = TaskHistory::open(dir.path(), 3).unwrap();
+    history.push(record("t1", TaskStatus::Done, at(1)));
+    history.push(record("t2", TaskStatus::Failed, at(2)));
+    history.push(record("t3", TaskStatus::Done, at(3)));
+    history.push(record("t4", TaskStatus::Done, at(4)));
+
+    // the oldest one is dropped, the most recent comes first
+    assert_eq!(
+        ids(history.list(&TaskFilter::default())),
+        ["t4", "t3", "t2"]
+    );
+    let done = TaskFilter {
+        status: Some(TaskStatus::Done),
+        ..TaskFilter::default()
+    };
+    assert_eq!(ids(history.list(&done)), ["t4", "t3"]);
+    let range = TaskFilter {
+        since: Some(at(2)),
+        until: Some(at(4)),
+        ..TaskFilter::default()
+    };
+    assert_eq!(ids(history.list(&range)), ["t3", "t2"]);
+    let latest = TaskFilter {
+        limit: Some(1),
+        ..TaskFilter::default()
+    };
+    assert_eq!(ids(history.list(&latest)), ["t4"]);
+
+    // reopened from the data dir, with less room
+    let reopened = TaskHistory::open(dir.path(), 2).unwrap();
+    assert_eq!(ids(reopened.list(&TaskFilter::default())), ["t4", "t3"]);
+
+    // a line cut by a crash is skipped, the file then only has the kept records
+    let path = dir.path().join(TASK_HISTORY_FILE);
+    let mut content = fs::read(&path).unwrap();
+    content.extend(b"{\"task_id\":\"t5\"");
+    fs::write(&path, content).unwrap();
+    let mut reopened = TaskHistory::open(dir.path(), 3).unwrap();
+    assert_eq!(ids(reopened.list(&TaskFilter::default())), ["t4", "t3"]);
+    reopened.push(record("t6", TaskStatus::Done, at(6)));
+    let lines = fs::read_to_string(&path).unwrap().lines().count();
+    assert_eq!(lines, 3);
+    let reopened = TaskHistory::open(dir.path(), 3).unwrap();
+    assert_eq!(
+        ids(reopened.list(&TaskFilter::default())),
+        ["t6", "t4", "t3"]
+    );
+}
+
+#[test]
+fn test_list_tasks() {
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec!["127.0.0.1:50260".parse::<ListenAddr>().unwrap()],
+    ));
+
+    let records = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50260", Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        c.set_miner_id("f01234");
+        let mut task = fake_window_post_task("history-task", 1);
+        task.proof_type = "NoSuchProof".to_string();
+        assert!(c.prove_task(task).await.is_err());
+
+        let client = new_client("http://127.0.0.1:50260", Duration::from_secs(10))
+            .await
+            .unwrap();
+        let list = |status: &str| {
+            let mut client = client.clone();
+            let req = Request::new(ListTasksRequest {
+                status: status.to_string(),
+                since: 0,
+                until: 0,
+                limit: 0,
+            });
+            async move { client.list_tasks(req).await }
+        };
+        assert!(list("Done").await.unwrap().into_inner().tasks.is_empty());
+        assert!(list("Finished").await.is_err());
+        let failed = list("Failed").await.unwrap().into_inner().tasks;
+        failed
+    });
+
+    assert_eq!(records.len(), 1);
+    let r = &records[0];
+    assert_eq!(r.task_id, "history-task");
+    assert_eq!(r.status, TaskStatus::Failed.to_string());
+    assert!(r.error.contains("unknown proof type"), "{}", r.error);
+    assert_eq!(r.client, "f01234");
+    assert!(r.submitted_at > 0 && r.submitted_at <= r.started_at && r.started_at <= r.ended_at);
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
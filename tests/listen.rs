@@ -0,0 +1,134 @@
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use tonic::Request;
+use window_post_snark_server::client;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server;
+use window_post_snark_server::server::WindowPostSnarkServer;
+use window_post_snark_server::snark_proof_grpc::GetWorkerStatusRequest;
+use window_post_snark_server::status::ServerStatus;
+
+#[test]
+fn test_parse_listen_addr() {
+    assert_eq!(
+        "0.0.0.0:50051".parse::<ListenAddr>().unwrap(),
+        ListenAddr::Tcp("0.0.0.0:50051".parse().unwrap())
+    );
+    assert_eq!(
+        "[::]:50051".parse::<ListenAddr>().unwrap(),
+        ListenAddr::Tcp("[::]:50051".parse().unwrap())
+    );
+    assert_eq!(
+        "unix:/tmp/snark.sock".parse::<ListenAddr>().unwrap(),
+        ListenAddr::Unix("/tmp/snark.sock".into())
+    );
+    assert_eq!(
+        ListenAddr::from_port("50052").unwrap().to_string(),
+        "0.0.0.0:50052"
+    );
+    assert!("unix:".parse::<ListenAddr>().is_err());
+    assert!("localhost".parse::<ListenAddr>().is_err());
+}
+
+#[test]
+fn test_unix_socket_listener() {
+    let dir = tempfile::tempdir().unwrap();
+    let sock = dir.path().join("snark.sock");
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, _run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    let handle = rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec![ListenAddr::Unix(sock.clone())],
+    ));
+
+    let addr = format!("unix:{}", sock.display());
+    let msg = rt.block_on(async {
+        // give the listener a moment to bind
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = client::new_client(&addr, Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.lock_server_if_free(Request::new(GetWorkerStatusRequest {
+            task_id: "unix-task".to_string(),
+        }))
+        .await
+        .unwrap()
+        .into_inner()
+        .msg
+    });
+    assert_eq!(msg, ServerStatus::Free.to_string());
+
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.block_on(async { handle.await.unwrap().unwrap() });
+    assert!(!sock.exists());
+}
+
+#[test]
+fn test_unix_socket_path_in_use() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("not-a-socket");
+    std::fs::write(&file, b"keep").unwrap();
+    let live = dir.path().join("live.sock");
+    let _listener = std::os::unix::net::UnixListener::bind(&live).unwrap();
+    let stale = dir.path().join("stale.sock");
+    drop(std::os::unix::net::UnixListener::bind(&stale).unwrap());
+
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, _run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    let handle = rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec![
+            ListenAddr::Unix(file.clone()),
+            ListenAddr::Unix(live.clone()),
+            ListenAddr::Unix(stale.clone()),
+        ],
+    ));
+
+    // the paths in use fail to bind without being removed, the stale socket is taken over
+    let addr = format!("unix:{}", stale.display());
+    rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        client::new_client(&addr, Duration::from_secs(10))
+            .await
+            .unwrap();
+    });
+    assert_eq!(std::fs::read(&file).unwrap(), b"keep");
+    assert!(std::os::unix::net::UnixStream::connect(&live).is_ok());
+
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.block_on(async { handle.await.unwrap().unwrap() });
+    assert!(!stale.exists());
+}
+
+#[test]
+fn test_no_listen_addr_bound() {
+    let dir = tempfile::tempdir().unwrap();
+    let file = dir.path().join("not-a-socket");
+    std::fs::write(&file, b"keep").unwrap();
+    let taken = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, _run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (_server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    // returns at once instead of running on no listener
+    let e = rt
+        .block_on(server::run_server(
+            server_exit_rx,
+            sv,
+            vec![
+                ListenAddr::Unix(file.clone()),
+                ListenAddr::Tcp(taken.local_addr().unwrap()),
+            ],
+        ))
+        .unwrap_err();
+    assert!(e.to_string().contains("no listen address"), "{}", e);
+    assert_eq!(std::fs::read(&file).unwrap(), b"keep");
+}
@@ -0,0 +1,33 @@
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::utils::{instance_name, lock_file_path, InstanceLock};
+
+#[test]
+fn test_instance_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let lock = InstanceLock::acquire_in(dir.path(), "0.0.0.0_50051").unwrap();
+    let pid = std::fs::read_to_string(&lock.path).unwrap();
+    assert_eq!(pid, std::process::id().to_string());
+
+    // the same instance is refused, another one runs beside it
+    let e = InstanceLock::acquire_in(dir.path(), "0.0.0.0_50051").unwrap_err();
+    assert!(e.to_string().contains(&pid), "{}", e);
+    let other = InstanceLock::acquire_in(dir.path(), "0.0.0.0_50052").unwrap();
+    assert_ne!(other.path, lock.path);
+
+    // closing the file releases it, as the exit of the process does, the stale file is reused
+    drop(lock);
+    assert!(lock_file_path(dir.path(), "0.0.0.0_50051").exists());
+    InstanceLock::acquire_in(dir.path(), "0.0.0.0_50051").unwrap();
+}
+
+#[test]
+fn test_instance_name() {
+    let addrs: Vec<ListenAddr> = vec![
+        "[::]:50051".parse().unwrap(),
+        "0.0.0.0:50052".parse().unwrap(),
+    ];
+    assert_eq!(instance_name(&addrs), "_____50051");
+    let unix: Vec<ListenAddr> = vec!["unix:/run/wdpost.sock".parse().unwrap()];
+    assert_eq!(instance_name(&unix), "unix__run_wdpost.sock");
+    assert_eq!(instance_name(&[]), "default");
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::fake_window_post_task;
+use serde_json::Value;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tracing::info;
+use tracing_subscriber::util::SubscriberInitExt;
+use window_post_snark_server::cpu::CpuSet;
+use window_post_snark_server::{logging, tasks};
+
+#[derive(Clone, Default)]
+struct Buf(Arc<Mutex<Vec<u8>>>);
+
+impl io::Write for Buf {
+    fn write(&mut self, b: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(b);
+        Ok(b.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_json_logs_carry_task_fields() {
+    let buf = Buf::default();
+    let writer = buf.clone();
+    logging::subscriber(true, move || writer.clone(), None)
+        .try_init()
+        .unwrap();
+
+    let mut t = tasks::set_task_info(&fake_window_post_task("logged-task", 1));
+    t.client = "f01234".to_string();
+    tasks::task_span(&t).in_scope(|| {
+        info!("proving");
+        // lines of the proving thread pool and of the log crate belong to the task too
+        let cpu_set = CpuSet {
+            threads: Some(1),
+            cores: vec![],
+        };
+        cpu_set.install(|| info!("in pool")).unwrap();
+        log::info!("from log crate");
+    });
+    info!("outside");
+
+    let out = String::from_utf8(buf.0.lock().unwrap().clone()).unwrap();
+    let lines: Vec<Value> = out
+        .lines()
+        .map(|l| serde_json::from_str(l).unwrap())
+        .collect();
+    let line = |msg: &str| {
+        lines
+            .iter()
+            .find(|l| l["fields"]["message"] == msg)
+            .unwrap_or_else(|| panic!("no line {} in {}", msg, out))
+    };
+    for msg in ["proving", "in pool", "from log crate"] {
+        let span = &line(msg)["span"];
+        assert_eq!(span["name"], "task", "{}", msg);
+        assert_eq!(span["task_id"], "logged-task");
+        assert_eq!(span["miner_id"], "f01234");
+        assert_eq!(span["sector_size"], 2048);
+        assert_eq!(span["proof_type"], "WindowPoSt");
+    }
+    assert!(line("outside").get("span").is_none());
+}
@@ -0,0 +1,119 @@
+mod common;
+
+use common::fake_window_post_task;
+use filecoin_proofs::SECTOR_SIZE_2_KIB;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::error::Error;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::memory;
+use window_post_snark_server::memory::MemoryEstimate;
+use window_post_snark_server::server::{ServerInfo, WindowPostSnarkServer};
+use window_post_snark_server::status::ServerStatus;
+use window_post_snark_server::{server, tasks};
+
+#[test]
+fn test_parse_memory() {
+    let meminfo =
+        "MemTotal:       65536000 kB\nMemFree:         1024000 kB\nMemAvailable:   32768000 kB\n";
+    assert_eq!(memory::parse_mem_available(meminfo), Some(32768000 << 10));
+    assert_eq!(memory::parse_mem_available("MemTotal: 1 kB\n"), None);
+    assert!(memory::mem_available().unwrap() > 0);
+
+    assert_eq!(memory::parse_bytes("12GiB").unwrap(), 12 << 30);
+    assert_eq!(memory::parse_bytes("512m").unwrap(), 512 << 20);
+    assert!(memory::parse_bytes("12PB").is_err());
+    assert_eq!(
+        memory::parse_memory_estimate("4GiB+12GiB").unwrap(),
+        MemoryEstimate {
+            base: 4 << 30,
+            per_partition: 12 << 30,
+        }
+    );
+    assert!(memory::parse_memory_estimate("4GiB").is_err());
+}
+
+#[test]
+fn test_check_memory() {
+    // 5 sectors of 2KiB are 3 window post partitions
+    let task = tasks::set_task_info(&fake_window_post_task("memory-task", 5));
+    let estimate = |per_partition: u64| {
+        let mut estimates = HashMap::new();
+        estimates.insert(
+            SECTOR_SIZE_2_KIB,
+            MemoryEstimate {
+                base: 0,
+                per_partition,
+            },
+        );
+        estimates
+    };
+    let mut si = ServerInfo::default();
+    assert!(memory::check_memory(&si, &task).is_ok());
+
+    let available = memory::mem_available().unwrap();
+    si.memory_estimates = estimate(available);
+    match memory::check_memory(&si, &task)
+        .unwrap_err()
+        .downcast::<Error>()
+    {
+        Ok(Error::InsufficientMemory(msg)) => assert!(msg.starts_with("3 partitions"), "{}", msg),
+        e => panic!("unexpected error: {:?}", e),
+    }
+    // partition workers only hold as many partitions at once as there are workers
+    si.memory_estimates = estimate(available / 4);
+    si.partition_workers = 2;
+    assert!(memory::check_memory(&si, &task).is_ok());
+}
+
+#[test]
+fn test_insufficient_memory_refused() {
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    let mut estimates = HashMap::new();
+    estimates.insert(
+        SECTOR_SIZE_2_KIB,
+        MemoryEstimate {
+            base: u64::MAX / 2,
+            per_partition: 0,
+        },
+    );
+    sv.set_memory_estimates(estimates).unwrap();
+    rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec!["127.0.0.1:50250".parse::<ListenAddr>().unwrap()],
+    ));
+
+    rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50250", Duration::from_secs(10))
+            .await
+            .unwrap();
+        let e = c
+            .prove_task(fake_window_post_task("big-task", 1))
+            .await
+            .unwrap_err();
+        match e.downcast::<Error>().unwrap() {
+            Error::InsufficientMemory(msg) => assert!(msg.contains("sector size 2048"), "{}", msg),
+            e => panic!("unexpected error: {}", e),
+        }
+        // the refused task does not keep the server locked
+        assert_eq!(c.try_lock("next-task").await.unwrap(), ServerStatus::Free);
+    });
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use common::{spawn_fake_server, FakeResult};
+use filecoin_proofs::{SECTOR_SIZE_2_KIB, SECTOR_SIZE_32_GIB, SECTOR_SIZE_512_MIB};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tonic::{Code, Request};
+use window_post_snark_server::client::{new_client, SnarkClient};
+use window_post_snark_server::error::Error;
+use window_post_snark_server::params::{parse_sector_size, verify_params, ParamFileState};
+use window_post_snark_server::server::SERVER_NOT_READY_MSG;
+use window_post_snark_server::snark_proof_grpc::GetWorkerStatusRequest;
+use window_post_snark_server::status::ServerStatus;
+
+#[test]
+fn test_parse_sector_size() {
+    assert_eq!(parse_sector_size("2KiB").unwrap(), SECTOR_SIZE_2_KIB);
+    assert_eq!(parse_sector_size("512MiB").unwrap(), SECTOR_SIZE_512_MIB);
+    assert_eq!(parse_sector_size("32GiB").unwrap(), SECTOR_SIZE_32_GIB);
+    assert_eq!(
+        parse_sector_size("34359738368").unwrap(),
+        SECTOR_SIZE_32_GIB
+    );
+    assert!(parse_sector_size("3KiB").is_err());
+    assert!(parse_sector_size("32TiB").is_err());
+    assert!(parse_sector_size("").is_err());
+}
+
+#[test]
+fn test_verify_params() {
+    // parameter cache dir is read once by storage-proofs settings, so set it before any params access
+    let dir = tempfile::tempdir().unwrap();
+    std::env::set_var("FIL_PROOFS_PARAMETER_CACHE", dir.path());
+
+    let reports = verify_params(&[SECTOR_SIZE_2_KIB]).unwrap();
+    assert_eq!(reports.len(), 2);
+    for r in &reports {
+        assert_eq!(r.state, ParamFileState::Missing);
+        assert!(r.path.starts_with(dir.path()));
+    }
+
+    std::fs::write(&reports[0].path, b"truncated").unwrap();
+    let reports = verify_params(&[SECTOR_SIZE_2_KIB]).unwrap();
+    assert_eq!(reports[0].state, ParamFileState::Corrupted);
+    assert_eq!(reports[1].state, ParamFileState::Missing);
+}
+
+#[test]
+fn test_not_ready_while_preloading() {
+    let rt = Runtime::new().unwrap();
+    let (exit_tx, srv_info) = spawn_fake_server(&rt, 50370, FakeResult::Proof(b"proof".to_vec()));
+    // as while params are still preloading
+    srv_info.lock().unwrap().ready = false;
+
+    rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let addr = "http://127.0.0.1:50370";
+        let mut raw = new_client(addr, Duration::from_secs(10)).await.unwrap();
+        let s = raw
+            .lock_server_if_free(Request::new(GetWorkerStatusRequest {
+                task_id: "early-task".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(s.code(), Code::Unavailable);
+        assert_eq!(s.message(), SERVER_NOT_READY_MSG);
+
+        let mut c = SnarkClient::connect(addr, Duration::from_secs(10))
+            .await
+            .unwrap();
+        assert!(!c.status().await.unwrap().ready);
+        match c
+            .try_lock("early-task")
+            .await
+            .unwrap_err()
+            .downcast::<Error>()
+        {
+            Ok(Error::ServerNotReady(_)) => {}
+            e => panic!("unexpected error: {:?}", e),
+        }
+
+        // params loaded
+        srv_info.lock().unwrap().ready = true;
+        assert!(c.status().await.unwrap().ready);
+        assert_eq!(c.try_lock("early-task").await.unwrap(), ServerStatus::Free);
+    });
+
+    exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
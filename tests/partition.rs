@@ -0,0 +1,43 @@
+mod common;
+
+use common::fake_window_post_task;
+use serde_json::{json, Value};
+use window_post_snark_server::error::Error;
+use window_post_snark_server::partition;
+
+#[test]
+fn test_split_window_post_task() {
+    // 2KiB window post proves 2 sectors per partition
+    let tasks = partition::split_window_post_task(&fake_window_post_task("split", 3)).unwrap();
+    assert_eq!(tasks.len(), 2);
+    for (k, t) in tasks.iter().enumerate() {
+        assert_eq!(t.task_id, format!("split-p{}", k));
+        let vanilla: Value = serde_json::from_slice(&t.vanilla_proof).unwrap();
+        assert_eq!(vanilla, json!([{ "sectors": [format!("vanilla-{}", k)] }]));
+        let pub_in: Value = serde_json::from_slice(&t.pub_in).unwrap();
+        assert_eq!(pub_in["k"], json!(k));
+        assert_eq!(pub_in["prover_id"], json!(vec![1u8; 32]));
+    }
+    let pub_in: Value = serde_json::from_slice(&tasks[0].pub_in).unwrap();
+    assert_eq!(pub_in["sectors"], json!([{ "id": 0 }, { "id": 1 }]));
+    assert_eq!(tasks[0].replicas_len, 2);
+    let pub_in: Value = serde_json::from_slice(&tasks[1].pub_in).unwrap();
+    assert_eq!(pub_in["sectors"], json!([{ "id": 2 }]));
+    assert_eq!(tasks[1].replicas_len, 1);
+
+    assert_eq!(
+        partition::merge_partition_proofs(vec![b"ab".to_vec(), b"cd".to_vec()]),
+        b"abcd".to_vec()
+    );
+}
+
+#[test]
+fn test_split_window_post_task_mismatch() {
+    let mut task = fake_window_post_task("mismatch", 3);
+    task.replicas_len = 5;
+    let e = partition::split_window_post_task(&task).unwrap_err();
+    match e.downcast::<Error>().unwrap() {
+        Error::InvalidParameters(_) => {}
+        e => panic!("unexpected error: {}", e),
+    }
+}
@@ -0,0 +1,97 @@
+mod common;
+
+use common::post::PoStTask;
+use filecoin_proofs::{SectorSize, SECTOR_SIZE_2_KIB};
+use std::fs;
+use std::process::Command;
+use window_post_snark_server::params;
+
+#[test]
+fn test_prove_cmd() {
+    let dir = tempfile::tempdir().unwrap();
+    // with_shape! has no tree shape for this sector size, so proving gets as far as run_snark
+    let mut post_config = params::window_post_config(SECTOR_SIZE_2_KIB);
+    post_config.sector_size = SectorSize(3 << 10);
+    fs::write(
+        dir.path().join("post_config.json"),
+        serde_json::to_vec(&post_config).unwrap(),
+    )
+    .unwrap();
+    fs::write(dir.path().join("vanilla.json"), b"[]").unwrap();
+    fs::write(dir.path().join("pub_in.json"), b"{}").unwrap();
+
+    let out = Command::new(env!("CARGO_BIN_EXE_window-post-snark-server"))
+        .current_dir(dir.path())
+        .args([
+            "prove",
+            "--vanilla",
+            "vanilla.json",
+            "--pub-in",
+            "pub_in.json",
+            "--post-config",
+            "post_config.json",
+            "--replicas-len",
+            "1",
+            "--out",
+            "proof.bin",
+            "--task-id",
+            "offline-task",
+        ])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(
+        stdout.contains("proving WindowPoSt task offline-task of 1 sectors"),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("timings: decode"), "{}", stdout);
+    assert!(
+        stderr.contains("panicked: unsupported sector size: 3072"),
+        "{}",
+        stderr
+    );
+    assert!(!dir.path().join("proof.bin").exists());
+}
+
+#[test]
+fn test_prove_cmd_2kib() {
+    // a single fake sector
+    let task = PoStTask::new(params::winning_post_config(SECTOR_SIZE_2_KIB), 1);
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("post_config.json"), task.post_config_json()).unwrap();
+    fs::write(dir.path().join("vanilla.json"), &task.vanilla_proof).unwrap();
+    fs::write(dir.path().join("pub_in.json"), &task.pub_in).unwrap();
+
+    // the child process finds the generated params through the inherited parameter cache dir
+    let out = Command::new(env!("CARGO_BIN_EXE_window-post-snark-server"))
+        .current_dir(dir.path())
+        .args([
+            "prove",
+            "--vanilla",
+            "vanilla.json",
+            "--pub-in",
+            "pub_in.json",
+            "--post-config",
+            "post_config.json",
+            "--replicas-len",
+            "1",
+            "--out",
+            "proof.bin",
+        ])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(stdout.contains("written to proof.bin"), "{}", stdout);
+    assert!(!stdout.contains("total 0ns"), "{}", stdout);
+
+    let proof = fs::read(dir.path().join("proof.bin")).unwrap();
+    assert!(task.verify(&proof));
+}
@@ -0,0 +1,143 @@
+mod common;
+
+use common::{spawn_fake_server, FakeResult};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tonic::Request;
+use window_post_snark_server::client;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::scheduler;
+use window_post_snark_server::scheduler::SnarkScheduler;
+use window_post_snark_server::server;
+use window_post_snark_server::snark_proof_grpc::{
+    GetTaskResultRequest, GetWorkerStatusRequest, SnarkTaskRequestParams,
+};
+use window_post_snark_server::status::ServerStatus;
+
+// the task result and the failures counted against each backend
+fn prove_through_scheduler(
+    first: FakeResult,
+    ports: &[u16],
+    scheduler_port: u16,
+) -> (Result<Vec<u8>, String>, Vec<u64>) {
+    let rt = Runtime::new().unwrap();
+    let mut exits = vec![];
+    for (i, port) in ports.iter().enumerate() {
+        // the first backend ends its task with `first`, the others return their port as proof
+        let result = if i == 0 {
+            first.clone()
+        } else {
+            FakeResult::Proof(port.to_be_bytes().to_vec())
+        };
+        exits.push(spawn_fake_server(&rt, *port, result).0);
+    }
+
+    let sc = SnarkScheduler::new(
+        ports
+            .iter()
+            .map(|p| format!("http://127.0.0.1:{}", p))
+            .collect(),
+    );
+    sc.scheduler_info.lock().unwrap().result_poll_interval = Duration::from_millis(100);
+    let sc_info = sc.scheduler_info.clone();
+    let (watch_exit_tx, watch_exit_rx) = oneshot::channel::<String>();
+    rt.spawn(scheduler::watch_backends(
+        watch_exit_rx,
+        sc.scheduler_info.clone(),
+        Duration::from_millis(100),
+    ));
+    let (sc_exit_tx, sc_exit_rx) = oneshot::channel::<String>();
+    rt.spawn(server::run_server(
+        sc_exit_rx,
+        sc,
+        vec![format!("127.0.0.1:{}", scheduler_port)
+            .parse::<ListenAddr>()
+            .unwrap()],
+    ));
+
+    let result = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let addr = format!("http://127.0.0.1:{}", scheduler_port);
+        let mut c = client::new_client(&addr, Duration::from_secs(10))
+            .await
+            .unwrap();
+        let task_id = "scheduled-task".to_string();
+        let msg = c
+            .lock_server_if_free(Request::new(GetWorkerStatusRequest {
+                task_id: task_id.clone(),
+            }))
+            .await
+            .unwrap()
+            .into_inner()
+            .msg;
+        assert_eq!(msg, ServerStatus::Free.to_string());
+        c.do_snark_task(Request::new(SnarkTaskRequestParams {
+            task_id: task_id.clone(),
+            vanilla_proof: vec![1],
+            pub_in: vec![2],
+            post_config: vec![3],
+            replicas_len: 1,
+            proof_type: String::default(),
+        }))
+        .await
+        .unwrap();
+        loop {
+            match c
+                .get_snark_task_result(Request::new(GetTaskResultRequest {
+                    task_id: task_id.clone(),
+                }))
+                .await
+            {
+                Ok(r) => {
+                    let r = r.into_inner();
+                    if r.msg == "ok" {
+                        return Ok(r.result);
+                    }
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+                Err(s) => return Err(s.message().to_string()),
+            }
+        }
+    });
+
+    watch_exit_tx.send("exit".to_string()).unwrap();
+    sc_exit_tx.send("exit".to_string()).unwrap();
+    for e in exits {
+        e.send("exit".to_string()).unwrap();
+    }
+    rt.shutdown_timeout(Duration::from_secs(1));
+    let failures = sc_info
+        .lock()
+        .unwrap()
+        .backends
+        .iter()
+        .map(|b| b.failures)
+        .collect();
+    (result, failures)
+}
+
+#[test]
+fn test_scheduler_fail_over() {
+    let (result, failures) = prove_through_scheduler(FakeResult::Lost, &[50161, 50162], 50160);
+    assert_eq!(result.unwrap(), 50162u16.to_be_bytes().to_vec());
+    assert_eq!(failures, vec![1, 0]);
+}
+
+#[test]
+fn test_scheduler_all_backends_failed() {
+    let (result, failures) = prove_through_scheduler(FakeResult::Lost, &[50171], 50170);
+    let err = result.unwrap_err();
+    assert!(err.contains("no task running"), "{}", err);
+    assert_eq!(failures, vec![scheduler::TASK_MAX_ATTEMPTS_DEFAULT as u64]);
+}
+
+#[test]
+fn test_scheduler_task_failed_not_retried() {
+    // the failing backend is the only free one when the task comes, a failed task is not
+    // tried on the other backend and does not count against the backend
+    let (result, failures) = prove_through_scheduler(FakeResult::Fail, &[50311, 50312], 50310);
+    let err = result.unwrap_err();
+    assert!(err.contains(common::FAKE_FAILURE), "{}", err);
+    assert_eq!(failures, vec![0, 0]);
+}
@@ -0,0 +1,88 @@
+mod common;
+
+use common::collector::{contains, spawn_collector};
+use common::{spawn_fake_server, FakeResult};
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::Context;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::scheduler::SnarkScheduler;
+use window_post_snark_server::{logging, scheduler, server, trace};
+
+const TRACE_ID: &str = "0af7651916cd43dd8448eb211c80319c";
+
+#[test]
+fn test_scheduler_passes_client_trace_on() {
+    let (endpoint, exports) = spawn_collector();
+    logging::init(false, Some(&endpoint));
+
+    let rt = Runtime::new().unwrap();
+    let (backend_exit_tx, _) = spawn_fake_server(&rt, 50361, FakeResult::Proof(b"proof".to_vec()));
+    let sc = SnarkScheduler::new(vec!["http://127.0.0.1:50361".to_string()]);
+    sc.scheduler_info.lock().unwrap().result_poll_interval = Duration::from_millis(100);
+    let (watch_exit_tx, watch_exit_rx) = oneshot::channel::<String>();
+    rt.spawn(scheduler::watch_backends(
+        watch_exit_rx,
+        sc.scheduler_info.clone(),
+        Duration::from_millis(100),
+    ));
+    let (sc_exit_tx, sc_exit_rx) = oneshot::channel::<String>();
+    rt.spawn(server::run_server(
+        sc_exit_rx,
+        sc,
+        vec!["127.0.0.1:50360".parse::<ListenAddr>().unwrap()],
+    ));
+
+    // the trace of a miner calling the scheduler
+    let miner_span = SpanContext::new(
+        TraceId::from_hex(TRACE_ID).unwrap(),
+        SpanId::from_hex("b7ad6b7169203331").unwrap(),
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    );
+    let guard = Context::new().with_remote_span_context(miner_span).attach();
+    let proof = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50360", Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        c.prove("scheduled-task", vec![1], vec![2], vec![3], 1)
+            .await
+            .unwrap()
+    });
+    drop(guard);
+    assert_eq!(proof, b"proof".to_vec());
+
+    watch_exit_tx.send("exit".to_string()).unwrap();
+    sc_exit_tx.send("exit".to_string()).unwrap();
+    backend_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+    trace::shutdown();
+
+    // the rpc spans of the scheduler, its dispatch span and the rpc spans of the backend
+    // all belong to the trace of the miner
+    let trace_id = TraceId::from_hex(TRACE_ID).unwrap().to_bytes();
+    let exports = exports.lock().unwrap();
+    let spans = |marker: &[u8]| {
+        exports
+            .iter()
+            .filter(|body| contains(body, marker))
+            .collect::<Vec<_>>()
+    };
+    let dispatched = spans(b"dispatch");
+    assert!(!dispatched.is_empty(), "no dispatch span");
+    assert!(dispatched.iter().all(|body| contains(body, &trace_id)));
+    // one on the scheduler and one on the backend
+    let submitted = spans(b"DoSnarkTask");
+    assert!(
+        submitted.len() >= 2,
+        "{} DoSnarkTask spans",
+        submitted.len()
+    );
+    assert!(submitted.iter().all(|body| contains(body, &trace_id)));
+}
@@ -14,6 +14,7 @@ use window_post_snark_server::server::WindowPostSnarkServer;
 use window_post_snark_server::client;
 use window_post_snark_server::snark_proof_grpc::{GetTaskResultRequest, GetWorkerStatusRequest, UnlockServerRequest};
 use window_post_snark_server::run;
+use window_post_snark_server::listen::ListenAddr;
 
 async fn listen_exit_signal() {
     let term = Arc::new(AtomicBool::new(false));
@@ -36,16 +37,16 @@ fn run_s() {
     let (run_task_tx, _) = mpsc::unbounded_channel::<String>();
     let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
     let sv = WindowPostSnarkServer::new(run_task_tx);
-    let handle = rt.spawn(server::run_server(server_exit_rx, sv, "50051".to_string()));
+    let handle = rt.spawn(server::run_server(server_exit_rx, sv, vec![ListenAddr::from_port("50051").unwrap()]));
 
     rt.block_on(listen_exit_signal());
     server_exit_tx.send("exit".to_string()).unwrap();
-    rt.block_on(async { handle.await.unwrap() });
+    rt.block_on(async { handle.await.unwrap().unwrap() });
     rt.shutdown_background();
 }
 
 fn run_all() {
-    run::run("50051".to_string(),Duration::from_secs(20),Duration::from_secs(100),Duration::from_secs(200))
+    run::run(run::RunConfig{server_lock_time_out:Duration::from_secs(20),server_task_get_back_time_out:Duration::from_secs(100),server_exit_time_out_after_task_done:Duration::from_secs(200),..run::RunConfig::default()})
 }
 
 #[test]
@@ -0,0 +1,116 @@
+mod common;
+
+use common::{spawn_fake_server, FakeResult};
+use std::thread;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::history::TaskFilter;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::run;
+use window_post_snark_server::run::RunConfig;
+use window_post_snark_server::server::WindowPostSnarkServer;
+use window_post_snark_server::status::{ServerStatus, ShutdownPolicy, TaskStatus};
+use window_post_snark_server::tasks;
+
+#[test]
+fn test_shutdown_with_admin_token() {
+    let server = thread::spawn(|| {
+        run::run(RunConfig {
+            listen_addrs: vec![ListenAddr::from_port("50280").unwrap()],
+            admin_token: Some("secret".to_string()),
+            ..RunConfig::default()
+        })
+    });
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50280", Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        // even a local client needs the token once the server has one
+        assert!(c.shutdown(ShutdownPolicy::Immediate, None).await.is_err());
+        assert!(c
+            .shutdown(ShutdownPolicy::Immediate, Some("wrong"))
+            .await
+            .is_err());
+        assert_eq!(c.status().await.unwrap().status, "Free");
+
+        let accepted = c
+            .shutdown(ShutdownPolicy::AfterResult, Some("secret"))
+            .await
+            .unwrap();
+        assert_eq!(accepted.status, "Free");
+        let last = c.wait_exit(Some(Duration::from_secs(20))).await.unwrap();
+        assert!(last.is_some());
+    });
+    server.join().unwrap();
+}
+
+#[test]
+fn test_no_task_accepted_once_shutting_down() {
+    let rt = Runtime::new().unwrap();
+    let (_exit_tx, _) = spawn_fake_server(&rt, 50281, FakeResult::Proof(vec![1]));
+    let status = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50281", Duration::from_secs(10))
+            .await
+            .unwrap();
+        // a local server without an admin token takes it without one
+        c.shutdown(ShutdownPolicy::AfterResult, None).await.unwrap();
+        c.try_lock("late-task").await.unwrap()
+    });
+    assert_eq!(status, ServerStatus::Unknown);
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
+
+// whether the task worker exits within a second, with the task of the server in `task_status`
+fn exits_at_once(policy: ShutdownPolicy, task_status: TaskStatus) -> (bool, Vec<TaskStatus>) {
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    {
+        let mut si = sv.server_info.lock().unwrap();
+        si.status = ServerStatus::Working;
+        si.task_info.task_id = "shutdown-task".to_string();
+        si.task_info.task_status = task_status;
+        si.shutdown = Some(policy);
+    }
+    let worker = rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    task_exit_tx.send("exit".to_string()).unwrap();
+    let exited = rt.block_on(async { tokio::time::timeout(Duration::from_secs(1), worker).await });
+    let history = sv
+        .server_info
+        .lock()
+        .unwrap()
+        .history
+        .lock()
+        .unwrap()
+        .list(&TaskFilter::default())
+        .into_iter()
+        .map(|r| r.status)
+        .collect();
+    rt.shutdown_timeout(Duration::from_secs(1));
+    (exited.is_ok(), history)
+}
+
+#[test]
+fn test_shutdown_policies() {
+    // a done task waits for the miner to get its result back, unless asked otherwise
+    assert!(!exits_at_once(ShutdownPolicy::AfterResult, TaskStatus::Done).0);
+    assert!(exits_at_once(ShutdownPolicy::AfterTask, TaskStatus::Done).0);
+    assert!(!exits_at_once(ShutdownPolicy::AfterTask, TaskStatus::Working).0);
+    // a proving task is abandoned and kept in the history as failed
+    assert_eq!(
+        exits_at_once(ShutdownPolicy::Immediate, TaskStatus::Working),
+        (true, vec![TaskStatus::Failed])
+    );
+}
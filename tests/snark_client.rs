@@ -0,0 +1,215 @@
+mod common;
+
+use common::{fake_window_post_task, spawn_fake_server, FakeResult, FAKE_FAILURE};
+use filecoin_proofs::SECTOR_SIZE_2_KIB;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use window_post_snark_server::client::{SnarkClient, SnarkClientPool};
+use window_post_snark_server::error::Error;
+use window_post_snark_server::memory::MemoryEstimate;
+
+fn prove_on(port: u16, result: FakeResult, lock_by_another_task: bool) -> anyhow::Result<Vec<u8>> {
+    let rt = Runtime::new().unwrap();
+    let (exit_tx, _) = spawn_fake_server(&rt, port, result);
+    let proved = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let addr = format!("http://127.0.0.1:{}", port);
+        let mut c = SnarkClient::connect(&addr, Duration::from_secs(10)).await?;
+        c.set_lock_wait_time_out(Duration::from_secs(1));
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        if lock_by_another_task {
+            c.lock("another-task").await?;
+        }
+        c.prove("client-task", vec![1], vec![2], vec![3], 1).await
+    });
+    exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+    proved
+}
+
+#[test]
+fn test_snark_client_prove() {
+    assert_eq!(
+        prove_on(50180, FakeResult::Proof(b"proof".to_vec()), false).unwrap(),
+        b"proof".to_vec()
+    );
+}
+
+#[test]
+fn test_snark_client_task_failed() {
+    let e = prove_on(50181, FakeResult::Fail, false).unwrap_err();
+    match e.downcast::<Error>().unwrap() {
+        Error::TaskFailedWithError(msg) => assert_eq!(msg, FAKE_FAILURE),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_snark_client_server_busy() {
+    let e = prove_on(50182, FakeResult::Proof(b"proof".to_vec()), true).unwrap_err();
+    match e.downcast::<Error>().unwrap() {
+        Error::ServerBusy(status) => assert_eq!(status, "Locked"),
+        e => panic!("unexpected error: {}", e),
+    }
+}
+
+#[test]
+fn test_snark_client_pool_fail_over() {
+    let rt = Runtime::new().unwrap();
+    let (failing_exit_tx, _) = spawn_fake_server(&rt, 50191, FakeResult::Lost);
+    let (ok_exit_tx, _) = spawn_fake_server(&rt, 50192, FakeResult::Proof(b"proof".to_vec()));
+    // nothing listens on 50190
+    let addrs = vec![50190, 50191, 50192]
+        .into_iter()
+        .map(|p| format!("http://127.0.0.1:{}", p))
+        .collect();
+    let mut pool = SnarkClientPool::new(addrs, Duration::from_secs(1));
+    pool.set_lock_wait_time_out(Duration::from_secs(2));
+    pool.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+    pool.set_health_policy(1, Duration::from_secs(60));
+
+    let proof = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        pool.prove("pool-task", vec![1], vec![2], vec![3], 1).await
+    });
+    assert_eq!(proof.unwrap(), b"proof".to_vec());
+
+    let stats = pool.stats().unwrap();
+    assert!(stats[0].failures > 0);
+    assert!(!stats[0].is_healthy());
+    assert_eq!(stats[2].consecutive_failures, 0);
+    assert!(stats[2].latency.is_some());
+
+    failing_exit_tx.send("exit".to_string()).unwrap();
+    ok_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
+
+#[test]
+fn test_snark_client_pool_task_failed() {
+    let rt = Runtime::new().unwrap();
+    let (exit_tx, _) = spawn_fake_server(&rt, 50321, FakeResult::Fail);
+    let mut pool = SnarkClientPool::new(
+        vec!["http://127.0.0.1:50321".to_string()],
+        Duration::from_secs(1),
+    );
+    pool.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+    pool.set_health_policy(1, Duration::from_secs(60));
+
+    let e = rt
+        .block_on(async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            pool.prove("failed-task", vec![1], vec![2], vec![3], 1)
+                .await
+        })
+        .unwrap_err();
+    match e.downcast::<Error>().unwrap() {
+        Error::TaskFailedWithError(msg) => assert_eq!(msg, FAKE_FAILURE),
+        e => panic!("unexpected error: {}", e),
+    }
+    // a failed task is not the server's fault
+    let stats = pool.stats().unwrap();
+    assert_eq!(stats[0].failures, 0);
+    assert!(stats[0].is_healthy());
+
+    exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
+
+#[test]
+fn test_snark_client_pool_insufficient_memory() {
+    let rt = Runtime::new().unwrap();
+    let (short_exit_tx, short_info) =
+        spawn_fake_server(&rt, 50331, FakeResult::Proof(b"short".to_vec()));
+    short_info.lock().unwrap().memory_estimates = {
+        let mut estimates = HashMap::new();
+        estimates.insert(
+            SECTOR_SIZE_2_KIB,
+            MemoryEstimate {
+                base: u64::MAX / 2,
+                per_partition: 0,
+            },
+        );
+        estimates
+    };
+    let (ok_exit_tx, _) = spawn_fake_server(&rt, 50332, FakeResult::Proof(b"proof".to_vec()));
+    let addrs = vec![50331, 50332]
+        .into_iter()
+        .map(|p| format!("http://127.0.0.1:{}", p))
+        .collect();
+    let mut pool = SnarkClientPool::new(addrs, Duration::from_secs(1));
+    pool.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+    pool.set_health_policy(1, Duration::from_secs(60));
+    // a server short of memory takes no attempt
+    pool.set_max_attempts(1);
+
+    let task = fake_window_post_task("memory-task", 1);
+    let proof = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        // the server short of memory is tried first, the other one is free a bit later
+        let mut other = SnarkClient::connect("http://127.0.0.1:50332", Duration::from_secs(10))
+            .await
+            .unwrap();
+        other.lock("another-task").await.unwrap();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            other.unlock("another-task").await.unwrap();
+        });
+        pool.prove(
+            &task.task_id,
+            task.vanilla_proof,
+            task.pub_in,
+            task.post_config,
+            task.replicas_len,
+        )
+        .await
+    });
+    assert_eq!(proof.unwrap(), b"proof".to_vec());
+    let stats = pool.stats().unwrap();
+    assert!(stats[0].requests > 0);
+    assert_eq!(stats[0].failures, 0);
+    assert!(stats[0].is_healthy());
+
+    short_exit_tx.send("exit".to_string()).unwrap();
+    ok_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
+
+#[test]
+fn test_snark_client_pool_prove_partitioned() {
+    let rt = Runtime::new().unwrap();
+    let exits: Vec<_> = vec![50200, 50201]
+        .into_iter()
+        .map(|p| spawn_fake_server(&rt, p, FakeResult::Echo).0)
+        .collect();
+    let addrs = vec![50200, 50201]
+        .into_iter()
+        .map(|p| format!("http://127.0.0.1:{}", p))
+        .collect();
+    let mut pool = SnarkClientPool::new(addrs, Duration::from_secs(1));
+    pool.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+
+    let task = fake_window_post_task("partitioned-task", 5);
+    let proof = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        pool.prove_partitioned(
+            &task.task_id,
+            task.vanilla_proof,
+            task.pub_in,
+            task.post_config,
+            task.replicas_len,
+        )
+        .await
+    });
+    // the fake servers echo each partition's vanilla proof back
+    let expected: Vec<u8> = (0..3)
+        .flat_map(|k| format!(r#"[{{"sectors":["vanilla-{}"]}}]"#, k).into_bytes())
+        .collect();
+    assert_eq!(proof.unwrap(), expected);
+
+    for e in exits {
+        e.send("exit".to_string()).unwrap();
+    }
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
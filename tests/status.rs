@@ -1,16 +1,85 @@
-use window_post_snark_server::status::{ServerStatus, TaskStatus};
+mod common;
 
-#[test]
-fn test_enum() {
-    assert_eq!(
-        String::from("Unknown"),
-        (ServerStatus::Unknown).to_string().as_ref()
-    );
-    assert_eq!(
-        String::from("Free"),
-        (ServerStatus::Free.clone()).to_string().as_ref()
+use common::fake_window_post_task;
+use serde_json::Value;
+use std::process::Command;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server::WindowPostSnarkServer;
+use window_post_snark_server::{server, tasks};
+
+const ADDR: &str = "http://127.0.0.1:50290";
+
+fn status_cmd(args: &[&str]) -> String {
+    let out = Command::new(env!("CARGO_BIN_EXE_window-post-snark-server"))
+        .args(["status", "--addr", ADDR])
+        .args(args)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
     );
-    println!("{:?}", ServerStatus::Working);
-    println!("{}", ServerStatus::default().to_string());
-    println!("{}", TaskStatus::default().to_string())
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn test_status_cmd() {
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec!["127.0.0.1:50290".parse::<ListenAddr>().unwrap()],
+    ));
+    let mut c = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect(ADDR, Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        let mut task = fake_window_post_task("failed-task", 1);
+        task.proof_type = "NoSuchProof".to_string();
+        assert!(c.prove_task(task).await.is_err());
+        c
+    });
+
+    let s: Value = serde_json::from_str(&status_cmd(&["--json", "--failures", "3"])).unwrap();
+    assert_eq!(s["status"], "Free");
+    assert_eq!(s["task_id"], "failed-task");
+    assert_eq!(s["task_status"], "Failed");
+    assert_eq!(s["lock_time_out_ms"], 10000);
+    assert_eq!(s["max_prove_time_ms"], 0);
+    assert_eq!(s["shutdown"], "");
+    let failures = s["recent_failures"].as_array().unwrap();
+    assert_eq!(failures.len(), 1);
+    assert_eq!(failures[0]["task_id"], "failed-task");
+    assert!(failures[0]["error"]
+        .as_str()
+        .unwrap()
+        .contains("unknown proof type"));
+
+    let text = status_cmd(&[]);
+    assert!(text.contains("status:     Free"), "{}", text);
+    assert!(text.contains("failed-task NoSuchProof"), "{}", text);
+
+    // reading the status never locks the server
+    let status = rt.block_on(async { c.status().await.unwrap() });
+    assert_eq!(status.status, "Free");
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
 }
@@ -0,0 +1,76 @@
+mod common;
+
+use common::{spawn_fake_server, FakeResult};
+use std::fs;
+use std::process::{Command, Output};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+
+fn submit_cmd(dir: &std::path::Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_window-post-snark-server"))
+        .current_dir(dir)
+        .args(["submit", "--addr", "http://127.0.0.1:50300"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_submit_cmd() {
+    let rt = Runtime::new().unwrap();
+    let (exit_tx, _) = spawn_fake_server(&rt, 50300, FakeResult::Echo);
+    rt.block_on(async { tokio::time::sleep(Duration::from_millis(500)).await });
+
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("vanilla.json"), b"vanilla proof").unwrap();
+    fs::write(dir.path().join("pub_in.json"), b"{}").unwrap();
+
+    // the proof type follows --post-config, without one it has to be given
+    let out = submit_cmd(
+        dir.path(),
+        &[
+            "--vanilla",
+            "vanilla.json",
+            "--pub-in",
+            "pub_in.json",
+            "--replicas-len",
+            "1",
+            "--out",
+            "proof.bin",
+        ],
+    );
+    assert!(!out.status.success());
+    assert!(!dir.path().join("proof.bin").exists());
+
+    let out = submit_cmd(
+        dir.path(),
+        &[
+            "--vanilla",
+            "vanilla.json",
+            "--pub-in",
+            "pub_in.json",
+            "--proof-type",
+            "WindowPoSt",
+            "--replicas-len",
+            "1",
+            "--out",
+            "proof.bin",
+            "--task-id",
+            "submitted-task",
+        ],
+    );
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.contains("submitted-task"), "{}", stdout);
+    assert_eq!(
+        fs::read(dir.path().join("proof.bin")).unwrap(),
+        b"vanilla proof"
+    );
+
+    exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
@@ -0,0 +1,132 @@
+mod common;
+
+use common::{fake_window_post_task, spawn_fake_server, FakeResult};
+use filecoin_proofs::SECTOR_SIZE_2_KIB;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use tonic::Request;
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::error::Error;
+use window_post_snark_server::handler::TaskContext;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server::{ServerInfo, WindowPostSnarkServer};
+use window_post_snark_server::snark_proof_grpc::GetServerStatusRequest;
+use window_post_snark_server::status::ServerStatus;
+use window_post_snark_server::timings::PhaseTimings;
+use window_post_snark_server::{server, tasks};
+
+#[test]
+fn test_max_prove_time() {
+    // a prove worker that never answers stands in for a stuck proof
+    let dir = tempfile::tempdir().unwrap();
+    let exe = dir.path().join("hanging-worker");
+    std::fs::write(&exe, "#!/bin/sh\nexec sleep 60\n").unwrap();
+    std::fs::set_permissions(&exe, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    sv.server_info.lock().unwrap().prove_worker_exe = Some(exe);
+    // the sector size limit wins over the global one
+    let mut by_sector_size = HashMap::new();
+    by_sector_size.insert(SECTOR_SIZE_2_KIB, Duration::from_secs(1));
+    sv.set_max_prove_time(Some(Duration::from_secs(3600)), by_sector_size)
+        .unwrap();
+    let srv_info = sv.server_info.clone();
+    rt.spawn(tasks::run_task(task_exit_rx, run_task_rx, srv_info.clone()));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec!["127.0.0.1:50240".parse::<ListenAddr>().unwrap()],
+    ));
+
+    let timed_out = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50240", Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        // the server is free again after each timed out task
+        for id in ["slow-task-1", "slow-task-2"] {
+            let e = c
+                .prove_task(fake_window_post_task(id, 1))
+                .await
+                .unwrap_err();
+            match e.downcast::<Error>().unwrap() {
+                Error::TaskFailedWithError(msg) => {
+                    assert!(msg.contains(&format!("task {} timed out", id)), "{}", msg)
+                }
+                e => panic!("unexpected error: {}", e),
+            }
+        }
+        let mut client = window_post_snark_server::client::new_client(
+            "http://127.0.0.1:50240",
+            Duration::from_secs(10),
+        )
+        .await
+        .unwrap();
+        client
+            .get_server_status(Request::new(GetServerStatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner()
+            .tasks_timed_out
+    });
+    assert_eq!(timed_out, 2);
+    // the hanging workers were killed
+    assert!(srv_info.lock().unwrap().prove_worker_pid.is_none());
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
+
+#[test]
+fn test_timed_out_proof_writes_dropped() {
+    let srv_info = Arc::new(Mutex::new(ServerInfo {
+        task_generation: 1,
+        ..ServerInfo::default()
+    }));
+    let timed_out = TaskContext::new(srv_info.clone(), 1);
+    // the next task started while the timed out proof goes on
+    srv_info.lock().unwrap().task_generation = 2;
+    let current = TaskContext::new(srv_info.clone(), 2);
+    current.set_partitions(2);
+    timed_out.set_partitions(5);
+    timed_out.add_partitions_done(5);
+    timed_out.add_timings(&PhaseTimings {
+        prove: Duration::from_secs(10),
+        ..PhaseTimings::default()
+    });
+    current.add_partitions_done(1);
+
+    let si = srv_info.lock().unwrap();
+    assert_eq!(si.task_info.partitions, 2);
+    assert_eq!(si.task_info.partitions_done, 1);
+    assert_eq!(si.task_info.timings, PhaseTimings::default());
+}
+
+#[test]
+fn test_no_task_while_timed_out_proof_runs() {
+    let rt = Runtime::new().unwrap();
+    let (exit_tx, srv_info) = spawn_fake_server(&rt, 50241, FakeResult::Fail);
+    srv_info.lock().unwrap().orphaned_proof = true;
+    let statuses = rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50241", Duration::from_secs(10))
+            .await
+            .unwrap();
+        let held = c.try_lock("next-task").await.unwrap();
+        srv_info.lock().unwrap().orphaned_proof = false;
+        (held, c.try_lock("next-task").await.unwrap())
+    });
+    assert_eq!(statuses, (ServerStatus::Unknown, ServerStatus::Free));
+    exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
@@ -0,0 +1,27 @@
+use std::time::Duration;
+use window_post_snark_server::timings;
+use window_post_snark_server::timings::PhaseTimings;
+
+#[test]
+fn test_phase_timings() {
+    let mut partition = PhaseTimings::default();
+    let n = timings::timed(&mut partition.prove, || {
+        std::thread::sleep(Duration::from_millis(20));
+        7
+    });
+    assert_eq!(n, 7);
+    assert!(partition.prove >= Duration::from_millis(20));
+    partition.decode = Duration::from_millis(3);
+
+    // a task sums the phases of its partitions
+    let mut task = PhaseTimings::default();
+    task.add(&partition);
+    task.add(&partition);
+    assert_eq!(task.decode, Duration::from_millis(6));
+    assert_eq!(task.prove, partition.prove * 2);
+
+    let proto = task.to_proto();
+    assert_eq!(proto.decode_ms, 6);
+    assert_eq!(proto.prove_ms, task.prove.as_millis() as u64);
+    assert_eq!(proto.total_ms, 0);
+}
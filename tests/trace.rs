@@ -0,0 +1,87 @@
+mod common;
+
+use common::collector::{contains, spawn_collector};
+use common::fake_window_post_task;
+use opentelemetry::trace::{SpanContext, SpanId, TraceContextExt, TraceFlags, TraceId, TraceState};
+use opentelemetry::Context;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server::WindowPostSnarkServer;
+use window_post_snark_server::snark_proof_grpc::ListTasksRequest;
+use window_post_snark_server::{logging, server, tasks, trace};
+
+const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+#[test]
+fn test_spans_join_client_trace() {
+    let (endpoint, exports) = spawn_collector();
+    logging::init(false, Some(&endpoint));
+
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec!["127.0.0.1:50270".parse::<ListenAddr>().unwrap()],
+    ));
+
+    // the trace of a miner calling the client
+    let miner_span = SpanContext::new(
+        TraceId::from_hex(TRACE_ID).unwrap(),
+        SpanId::from_hex("00f067aa0ba902b7").unwrap(),
+        TraceFlags::SAMPLED,
+        true,
+        TraceState::default(),
+    );
+    let guard = Context::new().with_remote_span_context(miner_span).attach();
+    rt.block_on(async {
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        let mut c = SnarkClient::connect("http://127.0.0.1:50270", Duration::from_secs(10))
+            .await
+            .unwrap();
+        c.set_poll_interval(Duration::from_millis(100), Duration::from_millis(400));
+        let mut task = fake_window_post_task("traced-task", 1);
+        task.proof_type = "NoSuchProof".to_string();
+        assert!(c.prove_task(task).await.is_err());
+        c.status().await.unwrap();
+        c.list_tasks(ListTasksRequest::default()).await.unwrap();
+    });
+    drop(guard);
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+    trace::shutdown();
+
+    // one span per export, the rpc and task spans all belong to the trace of the miner
+    let trace_id = TraceId::from_hex(TRACE_ID).unwrap().to_bytes();
+    let exports = exports.lock().unwrap();
+    let spans = |marker: &[u8]| {
+        exports
+            .iter()
+            .filter(|body| contains(body, marker))
+            .collect::<Vec<_>>()
+    };
+    for marker in [
+        &b"DoSnarkTask"[..],
+        b"LockServerIfFree",
+        b"GetServerStatus",
+        b"ListTasks",
+        b"proof_type",
+    ] {
+        let found = spans(marker);
+        assert!(!found.is_empty(), "no span with {:?}", marker);
+        assert!(found.iter().all(|body| contains(body, &trace_id)));
+    }
+}
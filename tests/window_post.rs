@@ -0,0 +1,85 @@
+mod common;
+
+use common::post::PoStTask;
+use filecoin_proofs::SECTOR_SIZE_2_KIB;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use tonic::Request;
+use window_post_snark_server::client::{new_client, SnarkClient};
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server::WindowPostSnarkServer;
+use window_post_snark_server::snark_proof_grpc::{GetTaskResultRequest, SnarkTaskRequestParams};
+use window_post_snark_server::status::ProofType;
+use window_post_snark_server::{params, server, tasks};
+
+#[test]
+fn test_window_post_2kib_partition_workers() {
+    // 2 sectors per partition of 2KiB, the second partition is padded
+    let task = PoStTask::new(params::window_post_config(SECTOR_SIZE_2_KIB), 3);
+    assert_eq!(task.partitions, 2);
+
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    sv.set_partition_workers(2).unwrap();
+    rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec!["127.0.0.1:50340".parse::<ListenAddr>().unwrap()],
+    ));
+
+    let (proof, progress) = rt
+        .block_on(async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            let addr = "http://127.0.0.1:50340";
+            let mut c = SnarkClient::connect(addr, Duration::from_secs(10)).await?;
+            c.lock("window-task").await?;
+            c.submit(SnarkTaskRequestParams {
+                task_id: "window-task".to_string(),
+                vanilla_proof: task.vanilla_proof.clone(),
+                pub_in: task.pub_in.clone(),
+                post_config: task.post_config_json(),
+                replicas_len: 3,
+                proof_type: ProofType::WindowPoSt.to_string(),
+            })
+            .await?;
+            // the partitions done of every answer, the final one included
+            let mut raw = new_client(addr, Duration::from_secs(10)).await?;
+            let mut progress = vec![];
+            loop {
+                let r = raw
+                    .get_snark_task_result(Request::new(GetTaskResultRequest {
+                        task_id: "window-task".to_string(),
+                    }))
+                    .await?
+                    .into_inner();
+                progress.push((r.partitions_done, r.partitions));
+                if r.msg == "ok" {
+                    return anyhow::Ok((r.result, progress));
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        })
+        .unwrap();
+
+    // partitions are known once proving started and done ones only go up, to all of them
+    assert!(progress[0].0 < 2, "{:?}", progress);
+    assert_eq!(progress.last(), Some(&(2, 2)));
+    assert!(progress.windows(2).all(|w| w[0].0 <= w[1].0));
+    assert!(progress.iter().all(|(_, p)| *p == 0 || *p == 2));
+
+    // the partition proofs of both workers merge into the proof of the whole task
+    assert!(task.verify(&proof));
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::post::PoStTask;
+use filecoin_proofs::SECTOR_SIZE_2_KIB;
+use std::time::Duration;
+use tokio::runtime::Runtime;
+use tokio::sync::{mpsc, oneshot};
+use window_post_snark_server::client::SnarkClient;
+use window_post_snark_server::listen::ListenAddr;
+use window_post_snark_server::server::WindowPostSnarkServer;
+use window_post_snark_server::{params, server, tasks};
+
+#[test]
+fn test_winning_post_2kib() {
+    // a single fake sector
+    let task = PoStTask::new(params::winning_post_config(SECTOR_SIZE_2_KIB), 1);
+
+    let rt = Runtime::new().unwrap();
+    let (run_task_tx, run_task_rx) = mpsc::unbounded_channel::<String>();
+    let (server_exit_tx, server_exit_rx) = oneshot::channel::<String>();
+    let (task_exit_tx, task_exit_rx) = oneshot::channel::<String>();
+    let sv = WindowPostSnarkServer::new(run_task_tx);
+    rt.spawn(tasks::run_task(
+        task_exit_rx,
+        run_task_rx,
+        sv.server_info.clone(),
+    ));
+    rt.spawn(server::run_server(
+        server_exit_rx,
+        sv,
+        vec!["127.0.0.1:50210".parse::<ListenAddr>().unwrap()],
+    ));
+
+    let (proof, timings) = rt
+        .block_on(async {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+            // polls must keep answering within the rpc timeout while the server is proving
+            let mut c =
+                SnarkClient::connect("http://127.0.0.1:50210", Duration::from_secs(10)).await?;
+            c.set_poll_interval(Duration::from_millis(200), Duration::from_secs(1));
+            let proof = c
+                .prove(
+                    "winning-task",
+                    task.vanilla_proof.clone(),
+                    task.pub_in.clone(),
+                    task.post_config_json(),
+                    1,
+                )
+                .await?;
+            anyhow::Ok((proof, c.last_timings().cloned()))
+        })
+        .unwrap();
+
+    let timings = timings.unwrap();
+    assert!(timings.prove_ms > 0);
+    // only so as winning post is proved in a single call, phases of partitions proved
+    // in parallel are summed and may well add up to more than the wall clock total
+    assert!(
+        timings.total_ms
+            >= timings.decode_ms + timings.setup_ms + timings.params_load_ms + timings.prove_ms
+    );
+
+    assert!(task.verify(&proof));
+
+    task_exit_tx.send("exit".to_string()).unwrap();
+    server_exit_tx.send("exit".to_string()).unwrap();
+    rt.shutdown_timeout(Duration::from_secs(1));
+}